@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::yapko::{ObjectRef, Primitive, Scope, Value, WeakScope};
+
+// Number of suspects at which they are checked again
+const MIN_SUSPECTS: usize = 64;
+
+// Frees scopes which are kept alive only by the functions defined in them. A function keeps the scopes it was defined
+// in, and it is usually stored in one of them, so after the VM leaves such a scope the two still refer to each other.
+pub struct Collector {
+    // Scopes which were still used from elsewhere when the VM left them (e.g. by a returned function)
+    suspects: Vec<WeakScope>,
+    limit: usize,
+}
+
+impl Collector {
+    pub fn new() -> Collector {
+        Collector {
+            suspects: vec![],
+            limit: MIN_SUSPECTS,
+        }
+    }
+
+    // Scope was left by the VM, which still uses the live scopes
+    pub fn scope_left(&mut self, scope: Scope, live: &[Scope]) {
+        if Rc::strong_count(&scope) == 1 || scope.borrow().is_empty() {
+            return;
+        }
+        if !collect(&scope, live) {
+            self.suspects.push(Rc::downgrade(&scope));
+        }
+
+        if self.suspects.len() >= self.limit {
+            self.suspects.retain(|suspect| suspect.upgrade().is_some_and(|scope| !collect(&scope, live)));
+            // Suspects which stay alive are checked again once as many new ones were added
+            self.limit = MIN_SUSPECTS.max(self.suspects.len() * 2);
+        }
+    }
+}
+
+// Scope, object or the scopes a function was defined in, which functions bound to an object share
+enum Node {
+    Scope(Scope),
+    Object(ObjectRef),
+    Environment(Rc<[Scope]>),
+}
+
+impl Node {
+    fn address(&self) -> *const () {
+        match self {
+            Node::Scope(scope) => Rc::as_ptr(scope) as *const (),
+            Node::Object(object) => Rc::as_ptr(object) as *const (),
+            Node::Environment(environment) => Rc::as_ptr(environment) as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Scope(scope) => Rc::strong_count(scope),
+            Node::Object(object) => Rc::strong_count(object),
+            Node::Environment(environment) => Rc::strong_count(environment),
+        }
+    }
+
+    // Nodes this one holds a reference to
+    fn references(&self) -> Vec<Node> {
+        let object_node = |value: &Value| match value {
+            Value::Object(object) => Some(Node::Object(Rc::clone(object))),
+            _ => None
+        };
+        match self {
+            Node::Scope(scope) => scope.borrow().values().filter_map(object_node).collect(),
+            Node::Object(object) => {
+                let object = object.borrow();
+                let mut nodes: Vec<Node> = object.members.values().filter_map(object_node).collect();
+                if let Primitive::YapkoFunction(_, _, _, environment) = &object.value {
+                    nodes.push(Node::Environment(Rc::clone(environment)));
+                }
+                nodes
+            }
+            Node::Environment(environment) => environment.iter().cloned().map(Node::Scope).collect(),
+        }
+    }
+}
+
+// Empty the scope left by the VM, and every other scope reachable from it, when nothing outside of what is reachable
+// from it refers to it. Live scopes are still used by the VM and are not followed. Returns whether it was emptied.
+fn collect(root: &Scope, live: &[Scope]) -> bool {
+    let live: Vec<*const ()> = live.iter().map(|scope| Rc::as_ptr(scope) as *const ()).collect();
+
+    // Every node reachable from the root once, the references between them and how many of them each one has
+    let mut nodes = vec![Node::Scope(Rc::clone(root))];
+    let mut indexes = HashMap::from([(nodes[0].address(), 0)]);
+    let mut edges: Vec<Vec<usize>> = vec![];
+    let mut internal = vec![0];
+    let mut next = 0;
+    while next < nodes.len() {
+        let mut targets = vec![];
+        for node in nodes[next].references() {
+            let address = node.address();
+            if matches!(node, Node::Scope(_)) && live.contains(&address) {
+                continue;
+            }
+            let index = *indexes.entry(address).or_insert_with(|| {
+                nodes.push(node);
+                internal.push(0);
+                nodes.len() - 1
+            });
+            internal[index] += 1;
+            targets.push(index);
+        }
+        edges.push(targets);
+        next += 1;
+    }
+
+    // Nodes referred to from outside (by the VM, the stack or a value that is not reachable from the root) are
+    // alive, and so is everything reachable from them. Not counted are the copy held in nodes and the reference
+    // of the caller to the root.
+    let mut alive: Vec<bool> = nodes.iter().enumerate().map(
+        |(index, node)| node.strong_count() - 1 - usize::from(index == 0) > internal[index]
+    ).collect();
+    let mut pending: Vec<usize> = (0..nodes.len()).filter(|index| alive[*index]).collect();
+    while let Some(index) = pending.pop() {
+        for &target in &edges[index] {
+            if !alive[target] {
+                alive[target] = true;
+                pending.push(target);
+            }
+        }
+    }
+    if alive[0] {
+        return false;
+    }
+
+    // Values are dropped once no scope is borrowed anymore
+    let mut values = vec![];
+    for (node, alive) in nodes.iter().zip(alive) {
+        if let (Node::Scope(scope), false) = (node, alive) {
+            values.push(std::mem::take(&mut *scope.borrow_mut()));
+        }
+    }
+    drop(nodes);
+    drop(values);
+    true
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+use strum_macros::Display;
+use crate::diagnostic::Position;
+
+#[derive(Clone, Copy, Debug, Display, PartialEq)]
+pub enum ErrorKind {
+    // Variable or member does not exist (or already exists)
+    NameError,
+    // Value of a wrong type was used
+    TypeError,
+    // Function was called with a wrong number of arguments
+    ArityError,
+    DivisionByZero,
+    // Value has the right type, but cannot be used (e.g. "abc".toInt())
+    ValueError,
+    IOError,
+    // Code run by `execute` failed to compile
+    SyntaxError,
+    // Anything else that stops the program (e.g. 'return' outside of a function)
+    RuntimeError,
+}
+
+#[derive(Clone, Debug)]
+pub struct YapkoError {
+    pub kind: ErrorKind,
+    pub message: String,
+    // Offset of the instruction that failed
+    pub offset: usize,
+    // Source of the instruction that failed, if known
+    pub position: Option<Position>,
+    // Positions of calls leading to the error, innermost first
+    pub trace: Vec<Position>,
+}
+
+impl YapkoError {
+    pub fn new(kind: ErrorKind, message: String) -> YapkoError {
+        YapkoError {
+            kind,
+            message,
+            offset: 0,
+            position: None,
+            trace: vec![],
+        }
+    }
+
+    // Attach offset of the instruction that failed
+    pub fn at(mut self, offset: usize) -> YapkoError {
+        self.offset = offset;
+        self
+    }
+}
+
+impl fmt::Display for YapkoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.position {
+            Some(position) => write!(f, "{}: {}: {}", position, self.kind, self.message),
+            None => write!(f, "{} at instruction {}: {}", self.kind, self.offset, self.message)
+        }
+    }
+}
@@ -1,610 +1,1374 @@
 use std::collections::HashMap;
-use std::env::var;
-use std::process;
-use crate::{ByteCode, compile, get_file_content};
-use crate::yapko::{generate_boolean, generate_float, generate_int, generate_null, generate_string, generate_yapko_function, Variable, YapkoObject};
-use crate::yapko::Primitive::{Boolean, Function, YapkoFunction};
+use std::fs;
+use std::rc::Rc;
+use crate::{ByteCode, compile};
+use crate::bytecode::{Chunk, CONSTANT_SIZE, OpCode};
+use crate::collector::Collector;
+use crate::error::{ErrorKind, YapkoError};
+use crate::yapko::{
+    generate_range, generate_string, generate_types, generate_yapko_function, pop, NativeFunction, ObjectRef,
+    Primitive, Scope, Value, YapkoObject
+};
 
 pub struct VM {
-    stack: Vec<YapkoObject>,
-    pub(crate) scopes: Vec<HashMap<String, YapkoObject>>,
-    inside_function: bool
+    stack: Vec<Value>,
+    pub(crate) scopes: Vec<Scope>,
+    // Classes holding the methods of the built-in types
+    types: HashMap<String, ObjectRef>,
+    // Code being executed
+    code: Rc<Chunk>,
+    frames: Vec<Frame>,
+    // Frees the scopes which were left, but are still referred to by the functions defined in them
+    collector: Collector,
+}
+
+// What a call to a function defined in Yapko code does with `self` and its result
+#[derive(Clone, Copy, PartialEq)]
+enum CallKind {
+    Function,
+    // `self` is the object the method was called on
+    Method,
+    // `self` is the new instance, which is the result of the call
+    Constructor,
+    // Field initializers of a new instance, which continue with the frame below them instead of returning a value
+    Fields,
+}
+
+// State of a single function invocation (or a file run by `execute`)
+struct Frame {
+    // Where to continue after the call ends
+    return_code: Rc<Chunk>,
+    return_address: usize,
+    // First scope created by the call
+    base_scope: usize,
+    // Scopes visible where the function was defined, innermost last
+    environment: Rc<[Scope]>,
+    // Height of the stack below the arguments
+    stack_base: usize,
+    kind: CallKind,
+    // Frame of a file run by `execute` rather than a function
+    executed_file: bool,
 }
 
 impl VM {
     pub fn new() -> VM {
         VM {
             stack: vec![],
-            scopes: vec![HashMap::new()],
-            inside_function: false
+            scopes: vec![Scope::default()],
+            types: generate_types(),
+            code: Rc::new(Chunk::default()),
+            frames: vec![],
+            collector: Collector::new(),
         }
     }
 
-    pub fn interpret(&mut self, mut bytecode: Vec<u8>, commands: HashMap<u8, String>) {
-        let mut new_command = true;
-        let mut arguments:Vec<u8> = vec![];
-        let mut argument = String::new();
-        let mut command: u8 = 0;
-        let mut bytecode_of_function = vec![];
+    pub fn interpret(&mut self, chunk: Chunk) -> Result<(), YapkoError> {
+        self.code = Rc::new(chunk);
+        self.frames.clear();
+        self.run().map_err(|error| self.locate(error))
+    }
 
-        let mut current_scope = 0;
-        fn new_scope(scopes: &mut Vec<HashMap<String, YapkoObject>>, scope: &mut usize) {
-            *scope += 1;
-            scopes.push(HashMap::new());
+    // Attach source position of the failed instruction and of every call leading to it
+    fn locate(&self, mut error: YapkoError) -> YapkoError {
+        error.position = self.code.position_at(error.offset).cloned();
+        // Field initializers are not called by the code, they continue with the start of the next frame
+        for frame in self.frames.iter().rev().filter(|frame| frame.kind != CallKind::Fields) {
+            // Return address points right after the call
+            if let Some(position) = frame.return_code.position_at(frame.return_address - 1) {
+                error.trace.push(position.clone());
+            }
+        }
+        error
+    }
+
+    // Find scope in which the variable is visible from the current frame, and whether the current call created it
+    fn find_scope<'a>(
+        &'a self,
+        name: &str,
+        current_scope: usize,
+        frame: Option<&'a Frame>,
+    ) -> Option<(&'a Scope, bool)> {
+        let base_scope = frame.map_or(0, |frame| frame.base_scope);
+
+        // Scopes created by the current call
+        for scope in self.scopes[base_scope..=current_scope].iter().rev() {
+            if scope.borrow().contains_key(name) {
+                return Some((scope, true));
+            }
+        }
+
+        // Scopes visible where the function was defined
+        frame?.environment.iter().rev().find(|scope| scope.borrow().contains_key(name)).map(|scope| (scope, false))
+    }
+
+    // Find variable visible from the current frame: first in the scopes created by the call,
+    // then in the object the function was called on, then where the function was defined
+    fn find_variable(&self, name: &str, current_scope: usize, frame: Option<&Frame>) -> Option<Value> {
+        match self.find_scope(name, current_scope, frame) {
+            Some((scope, true)) => Some(scope.borrow()[name].clone()),
+            scope => self.find_member(name, frame).or_else(
+                || scope.map(|(scope, _)| scope.borrow()[name].clone())
+            )
         }
+    }
+
+    // Object the current method was called on
+    fn receiver(&self, frame: Option<&Frame>) -> Option<Value> {
+        self.scopes.get(frame?.base_scope)?.borrow().get("self").cloned()
+    }
+
+    // Find member of `self` of the current method, so fields and methods can be used without `self.`
+    fn find_member(&self, name: &str, frame: Option<&Frame>) -> Option<Value> {
+        let receiver = self.receiver(frame)?;
+        let Value::Object(object) = &receiver else {
+            return None;
+        };
+        let member = object.borrow().members.get(name).cloned()?;
+        Some(bind_member(member, &receiver))
+    }
 
-        fn end_scope(scopes: &mut Vec<HashMap<String, YapkoObject>>, scope: &mut usize, invoke_started_at_scope: usize, used_variables: &mut Vec<(usize, String)>) {
-            if scopes.len() > *scope {
-                scopes.remove(*scope);
+    // Store value into the variable found the same way as by find_variable, returns false if there is none
+    fn set_variable(&self, name: &str, value: Value, current_scope: usize) -> bool {
+        let frame = self.frames.last();
+        let scope = self.find_scope(name, current_scope, frame);
+        if let Some((scope, true)) = scope {
+            scope.borrow_mut().insert(name.to_string(), value);
+            return true;
+        }
+
+        if let Some(Value::Object(receiver)) = self.receiver(frame) {
+            if let Some(member) = receiver.borrow_mut().members.get_mut(name) {
+                *member = value;
+                return true;
             }
-            *scope -= 1;
-            if *scope == invoke_started_at_scope {
-                used_variables.clear();
+        }
+        match scope {
+            Some((scope, _)) => {
+                scope.borrow_mut().insert(name.to_string(), value);
+                true
             }
+            None => false
         }
+    }
 
-        fn operator_to_function_name(a: String) -> &'static str {
-            match a.as_str() {
-                "+" => "add",
-                "-" => "sub",
-                "*" => "mul",
-                "/" => "div",
-                "%" => "mod",
-                "<" => "smallerThan",
-                ">" => "greaterThan",
-                "<=" => "smallerOrEqual",
-                ">=" => "greaterOrEqual",
-                "==" => "equalTo",
-                &_ => {""}
+    // Scopes visible from the current frame, innermost last
+    fn environment(&self, current_scope: usize) -> Rc<[Scope]> {
+        let (base_scope, enclosing) = match self.frames.last() {
+            Some(frame) => (frame.base_scope, &frame.environment[..]),
+            None => (0, &[][..])
+        };
+        enclosing.iter().chain(&self.scopes[base_scope..=current_scope]).cloned().collect()
+    }
+
+    // Member of the value, values of built-in types share the methods of their class
+    fn lookup(&self, value: &Value, name: &str) -> Option<Value> {
+        let class = match value {
+            Value::Int(_) => self.types.get("Int"),
+            Value::Float(_) => self.types.get("Float"),
+            Value::Boolean(_) => self.types.get("Boolean"),
+            Value::Null => None,
+            Value::Object(object) => {
+                let object = object.borrow();
+                if let Some(member) = object.members.get(name) {
+                    return Some(member.clone());
+                }
+                self.types.get(&object.yapko_type)
             }
+        };
+        let member = class?.borrow().members.get(name).cloned();
+        member
+    }
+
+    // Start running a function defined in Yapko code, returns where its body starts
+    fn enter(
+        &mut self,
+        function: UserFunction,
+        receiver: Option<Value>,
+        kind: CallKind,
+        argument_count: usize,
+        return_address: usize,
+        current_scope: &mut usize,
+    ) -> Result<usize, YapkoError> {
+        let (function_code, start, parameter_count, environment) = function;
+        if argument_count != parameter_count {
+            return Err(YapkoError::new(
+                ErrorKind::ArityError,
+                format!("Expected {} arguments, but got {}", parameter_count, argument_count)
+            ));
         }
+        *current_scope += 1;
+        self.scopes.push(Scope::default());
 
-        let mut used_variables:Vec<(usize, String)> = Vec::new();
-        let mut current_function_argument = String::new();
-        let mut function_name = String::new();
-        let mut invoke_started_at_scope = 0;
+        if let Some(receiver) = receiver {
+            self.scopes[*current_scope].borrow_mut().insert(String::from("self"), receiver);
+        }
+        self.frames.push(Frame {
+            return_code: self.code.clone(),
+            return_address,
+            base_scope: *current_scope,
+            environment,
+            stack_base: self.stack.len() - argument_count,
+            kind,
+            executed_file: false,
+        });
+
+        self.code = function_code;
+        Ok(start)
+    }
+
+    // End the call of the frame and push its result, which is the new instance for a constructor, returns where
+    // to continue
+    fn leave(&mut self, frame: Frame, value: Option<Value>, current_scope: &mut usize) -> usize {
+        let instance = match frame.kind {
+            CallKind::Constructor => self.scopes[frame.base_scope].borrow_mut().remove("self"),
+            _ => None
+        };
+        // Remove every scope created by the call, including ones of nested blocks
+        let scopes = self.scopes.split_off(frame.base_scope);
+        self.stack.truncate(frame.stack_base);
+        *current_scope = frame.base_scope - 1;
+        self.code = frame.return_code;
+
+        if let Some(value) = instance.or(value).filter(|_| frame.kind != CallKind::Fields) {
+            self.stack.push(value);
+        }
+        for scope in scopes.into_iter().rev() {
+            self.collector.scope_left(scope, &self.scopes);
+        }
+        frame.return_address
+    }
+
+    // Start creating an instance of the class: the field initializers of its topmost superclass run first, then the
+    // ones of each subclass down to the class, then `init` gets the arguments. Returns where to continue.
+    fn construct(
+        &mut self,
+        class: &ObjectRef,
+        argument_count: usize,
+        return_address: usize,
+        current_scope: &mut usize,
+    ) -> Result<usize, YapkoError> {
+        if matches!(class.borrow().value, Primitive::NativeClass) {
+            return Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Cannot create instances of {}", class.borrow().name)
+            ));
+        }
+        let mut initializers = vec![];
+        let mut next = Some(Rc::clone(class));
+        while let Some(superclass) = next {
+            let superclass = superclass.borrow();
+            initializers.extend(superclass.members.get("$fields").and_then(user_function));
+            next = match superclass.members.get("$super") {
+                Some(Value::Object(superclass)) => Some(Rc::clone(superclass)),
+                _ => None
+            };
+        }
+
+        let class = class.borrow();
+        let instance = Value::new_object(YapkoObject {
+            name: String::new(),
+            yapko_type: class.name.clone(),
+            value: Primitive::None,
+            members: class.members.clone(),
+        });
+        // Every frame continues with the start of the one entered before it, the bottom one returns the instance
+        let mut address = return_address;
+        let mut kind = CallKind::Constructor;
+        match class.members.get("init").and_then(user_function) {
+            Some(init) => {
+                address = self.enter(init, Some(instance.clone()), kind, argument_count, address, current_scope)?;
+                kind = CallKind::Fields;
+            }
+            None if argument_count > 0 => {
+                return Err(YapkoError::new(
+                    ErrorKind::ArityError,
+                    format!("{} has no init, but got {} arguments", class.name, argument_count)
+                ));
+            }
+            None if initializers.is_empty() => self.stack.push(instance.clone()),
+            None => {}
+        }
+        for initializer in initializers {
+            address = self.enter(initializer, Some(instance.clone()), kind, 0, address, current_scope)?;
+            kind = CallKind::Fields;
+        }
+        Ok(address)
+    }
+
+    fn end_scope(&mut self, current_scope: &mut usize) {
+        if self.scopes.len() > *current_scope {
+            let scope = self.scopes.remove(*current_scope);
+            self.collector.scope_left(scope, &self.scopes);
+        }
+        *current_scope -= 1;
+    }
 
-        let mut inside_if = false;
-        let mut if_opened_at = 0;
+    fn run(&mut self) -> Result<(), YapkoError> {
+        let mut current_scope = 0;
+        fn new_scope(scopes: &mut Vec<Scope>, scope: &mut usize) {
+            *scope += 1;
+            scopes.push(Scope::default());
+        }
 
-        let mut inside_loop = false;
-        let mut loop_map: HashMap<usize, Vec<u8>> = HashMap::new();
-        let mut loop_started_at = 0;
-        let mut loop_current = 0;
+        fn operator_to_function_name(opcode: OpCode) -> &'static str {
+            match opcode {
+                OpCode::Add => "add",
+                OpCode::Sub => "sub",
+                OpCode::Mul => "mul",
+                OpCode::Div => "div",
+                OpCode::Mod => "mod",
+                OpCode::Less => "smallerThan",
+                OpCode::Greater => "greaterThan",
+                OpCode::LessEqual => "smallerOrEqual",
+                OpCode::GreaterEqual => "greaterOrEqual",
+                OpCode::Equal => "equalTo",
+                OpCode::NotEqual => "notEqualTo",
+                _ => ""
+            }
+        }
 
-        let mut inside_class = false;
-        let mut class_name = String::new();
+        // Method of the right operand computing the same result with the operands swapped: `1 + v` is `v.addFrom(1)`,
+        // `1 < v` is `v.greaterThan(1)` and `1 - v` is `v.subFrom(1)`
+        fn mirrored_operator(function_name: &str) -> &'static str {
+            match function_name {
+                "add" => "addFrom",
+                "sub" => "subFrom",
+                "mul" => "mulFrom",
+                "div" => "divFrom",
+                "mod" => "modFrom",
+                "equalTo" => "equalTo",
+                "notEqualTo" => "notEqualTo",
+                "smallerThan" => "greaterThan",
+                "greaterThan" => "smallerThan",
+                "smallerOrEqual" => "greaterOrEqual",
+                "greaterOrEqual" => "smallerOrEqual",
+                _ => ""
+            }
+        }
+
+        let mut current_function_argument = String::new();
+
+        let mut class_names: Vec<String> = vec![];
 
         let mut i = 0;
         loop {
-            if i >= bytecode.len() {
-                break;
+            if i >= self.code.code.len() {
+                // End of an executed file, go back to the file that executed it
+                match self.frames.pop() {
+                    Some(frame) => {
+                        self.code = frame.return_code;
+                        i = frame.return_address;
+                        continue;
+                    }
+                    None => break
+                }
             }
-            let byte = bytecode[i];
+            let instruction_start = i;
+            let opcode = OpCode::try_from(self.code.code[i]).map_err(|byte| YapkoError::new(
+                ErrorKind::RuntimeError,
+                format!("Unknown opcode {}", byte)
+            ).at(instruction_start))?;
             i += 1;
+            if opcode == OpCode::Nop {
+                continue;
+            }
 
-            if new_command {
-                if byte == 0 {
-                    continue;
-                }
-                command = byte;
-                if self.inside_function && commands[&command] != String::from("fun_end") {
-                    bytecode_of_function.push(byte);
-                }
-                new_command = false;
-            } else {
-                if byte == 0 && !self.inside_function && !inside_if && !inside_loop {
-                    match commands[&command].as_str() {
-                        "=" => {
-                            if self.stack.len() < 2 {
-                                println!("Missing arguments");
-                                return;
-                            }
-                            let index = self.stack.len();
-                            self.stack[index-1].name = String::from(&self.stack[index-2].name);
-                            if self.stack[index-2].parent == "" {
-                                for i in (0..=current_scope).rev() {
-                                    if self.scopes[i].contains_key(&self.stack[index - 2].name) {
-                                        *self.scopes[i].get_mut(
-                                            &self.stack[index - 2].name
-                                        ).unwrap() = self.stack[index - 1].clone();
-
-                                        break;
-                                    }
-                                }
-                            } else {
-                                for i in (0..=current_scope).rev() {
-                                    if self.scopes[i].contains_key(&*self.stack[index - 2].parent.clone()) {
-                                        let parent = self.scopes[i].get_mut(
-                                            &self.stack[index - 2].parent
-                                        ).unwrap().members.get_mut(
-                                            &self.stack[index - 2].name
-                                        ).unwrap();
-
-                                        if let Variable::YapkoObject(
-                                            parent
-                                        ) = parent {
-                                            *parent = self.stack[index-1].clone()
-                                        }
-                                        break;
-                                    }
-                                }
-                            }
+            // Keep the chunk of the instruction, calls and returns replace self.code
+            let code = Rc::clone(&self.code);
+            let operand = i;
+            i += opcode.operand_size();
+            // Constant the instruction refers to, empty if it has none
+            let argument = if opcode.has_constant() { code.constant(operand) } else { "" };
 
-                            for _ in 0..2 {
-                                self.stack.remove(self.stack.len()-1);
-                            }
-                        }
-                        "push_num" => {
-                            if argument.chars().all(|c| c.is_numeric()) {
-                                self.stack.push(
-                                    generate_int(
-                                        String::from("$int"),
-                                        argument.to_string().parse::<i32>().unwrap()
-                                    )
-                                );
-                            } else {
-                                self.stack.push(
-                                    generate_float(
-                                        String::from("$float"),
-                                        argument.to_string().parse::<f64>().unwrap()
-                                    )
-                                );
-                            }
+            match opcode {
+                OpCode::Define => {
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    if self.scopes[current_scope].borrow().contains_key(argument) {
+                        return Err(YapkoError::new(
+                            ErrorKind::NameError,
+                            format!("{} was already defined", argument)
+                        ).at(instruction_start));
+                    }
+                    self.scopes[current_scope].borrow_mut().insert(argument.to_string(), value);
+                }
+                OpCode::Set => {
+                    // Assignment is an expression, `a = b = 1` assigns the value again
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    self.stack.push(value.clone());
+                    if !self.set_variable(argument, value, current_scope) {
+                        return Err(YapkoError::new(
+                            ErrorKind::NameError,
+                            format!("'{}' not found", argument)
+                        ).at(instruction_start));
+                    }
+                }
+                OpCode::SetMember => {
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    let object = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    // Only existing members can be assigned, the change is seen through every reference
+                    match &object {
+                        Value::Object(target) if target.borrow().members.contains_key(argument) => {
+                            target.borrow_mut().members.insert(argument.to_string(), value.clone());
+                            self.stack.push(value);
                         }
-                        "push_str" => {
-                            self.stack.push(
-                                generate_string(
-                                    String::from("$string"),
-                                    argument.to_string()
-                                )
-                            );
+                        _ => {
+                            return Err(YapkoError::new(
+                                ErrorKind::NameError,
+                                format!("{} does not implement {}", object.yapko_type(), argument)
+                            ).at(instruction_start));
                         }
-                        "push_bool" => {
-                            self.stack.push(
-                                generate_boolean(
-                                    String::from("$bool"),
-                                    if argument == "1" {true} else {false}
-                                )
-                            );
+                    }
+                }
+                OpCode::PushInt => {
+                    self.stack.push(Value::Int(code.read_i32(operand)));
+                }
+                OpCode::PushFloat => {
+                    self.stack.push(Value::Float(code.read_f64(operand)));
+                }
+                OpCode::PushStr => {
+                    self.stack.push(generate_string(argument.to_string()));
+                }
+                OpCode::PushBool => {
+                    self.stack.push(Value::Boolean(code.read_u8(operand) != 0));
+                }
+                OpCode::PushNull => {
+                    self.stack.push(Value::Null);
+                }
+                OpCode::Pop => {
+                    pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                }
+                OpCode::Get => {
+                    if let Some(variable) = self.find_variable(argument, current_scope, self.frames.last()) {
+                        self.stack.push(variable);
+                    } else {
+                        return Err(YapkoError::new(
+                            ErrorKind::NameError,
+                            format!("'{}' not found", argument)
+                        ).at(instruction_start));
+                    }
+                }
+                OpCode::Call => {
+                    let argument_count = code.read_u8(operand) as usize;
+                    if self.stack.len() <= argument_count {
+                        return Err(YapkoError::new(
+                            ErrorKind::RuntimeError,
+                            String::from("Missing function to call")
+                        ).at(instruction_start));
+                    }
+                    let index = self.stack.len() - 1 - argument_count;
+                    let callee = self.stack.remove(index);
+                    if let Some(function) = native_function(&callee) {
+                        // Methods get the object they were taken from as their first argument
+                        let mut arguments = self.stack.split_off(index);
+                        if let Value::Object(method) = &callee {
+                            if let Some(receiver) = method.borrow().members.get("self") {
+                                arguments.insert(0, receiver.clone());
+                            }
                         }
-                        "get" => {
-                            if used_variables.len() == 0 {
-                                let mut index2 = 0;
-                                let mut contains = -1;
-                                for scope in &self.scopes {
-                                    if scope.contains_key(&*argument) {
-                                        contains = index2;
-                                    }
-                                    index2 += 1;
+                        let result = call_native(function, arguments).map_err(|error| error.at(instruction_start))?;
+                        self.stack.push(result);
+                    } else if let Some(function) = user_function(&callee) {
+                        // Methods carry the object they were taken from
+                        let receiver = match &callee {
+                            Value::Object(method) => method.borrow().members.get("self").cloned(),
+                            _ => None
+                        };
+                        let kind = if receiver.is_some() { CallKind::Method } else { CallKind::Function };
+                        i = self.enter(function, receiver, kind, argument_count, i, &mut current_scope)
+                            .map_err(|error| error.at(instruction_start))?;
+                    } else if callee.yapko_type() == "class" {
+                        let Value::Object(class) = &callee else {
+                            unreachable!("only objects are classes")
+                        };
+                        i = self.construct(class, argument_count, i, &mut current_scope)
+                            .map_err(|error| error.at(instruction_start))?;
+                    } else {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!("Cannot invoke {}", callee.yapko_type())
+                        ).at(instruction_start));
+                    }
+                }
+                OpCode::Add | OpCode::Sub | OpCode::Mul | OpCode::Div | OpCode::Mod | OpCode::Less |
+                OpCode::Greater | OpCode::LessEqual | OpCode::GreaterEqual | OpCode::Equal | OpCode::NotEqual => {
+                    let function_name = operator_to_function_name(opcode);
+
+                    if self.stack.len() < 2 {
+                        return Err(YapkoError::new(
+                            ErrorKind::RuntimeError,
+                            String::from("Missing operands")
+                        ).at(instruction_start));
+                    }
+                    let right = self.stack.pop().unwrap();
+                    let left = self.stack.pop().unwrap();
+
+                    // Method of a user class on the left, then the mirrored one on the right, then the native
+                    // implementation of the left operand, which does not know about instances of user classes
+                    let method = self.lookup(&left, function_name);
+                    let mirrored_name = mirrored_operator(function_name);
+                    let mirrored_method = self.lookup(&right, mirrored_name);
+                    if let Some(function) = method.as_ref().and_then(user_function) {
+                        self.stack.push(right);
+                        i = self.enter(function, Some(left), CallKind::Method, 1, i, &mut current_scope)
+                            .map_err(|error| error.at(instruction_start))?;
+                    } else if let Some(function) = mirrored_method.as_ref().and_then(user_function) {
+                        self.stack.push(left);
+                        i = self.enter(function, Some(right), CallKind::Method, 1, i, &mut current_scope)
+                            .map_err(|error| error.at(instruction_start))?;
+                    } else if let Some(function) = method.as_ref().and_then(native_function).filter(
+                        |_| !is_instance(&right)
+                    ) {
+                        let result = call_native(function, vec![left, right])
+                            .map_err(|error| error.at(instruction_start))?;
+                        self.stack.push(result);
+                    } else if is_instance(&right) {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!(
+                                "{} does not implement {}({}) and {} does not implement {}({})",
+                                left.yapko_type(), function_name, right.yapko_type(),
+                                right.yapko_type(), mirrored_name, left.yapko_type()
+                            )
+                        ).at(instruction_start));
+                    } else {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!(
+                                "Neither {} nor {} implements function '{}'",
+                                left.yapko_type(), right.yapko_type(), function_name
+                            )
+                        ).at(instruction_start));
+                    }
+                }
+                OpCode::Neg => {
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    let method = self.lookup(&value, "negate");
+                    if let Some(function) = method.as_ref().and_then(native_function) {
+                        let result = call_native(function, vec![value]).map_err(|error| error.at(instruction_start))?;
+                        self.stack.push(result);
+                    } else if let Some(function) = method.as_ref().and_then(user_function) {
+                        // Method of a user class, called like `value.negate()`
+                        i = self.enter(function, Some(value), CallKind::Method, 0, i, &mut current_scope)
+                            .map_err(|error| error.at(instruction_start))?;
+                    } else {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!("{} does not implement function 'negate'", value.yapko_type())
+                        ).at(instruction_start));
+                    }
+                }
+                OpCode::And | OpCode::Or | OpCode::Xor => {
+                    let right = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    let left = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    match (&left, &right) {
+                        (Value::Boolean(left_value), Value::Boolean(right_value)) => {
+                            let result = match opcode {
+                                OpCode::And => {
+                                    *left_value && *right_value
                                 }
-                                if contains >= 0 {
-                                    self.stack.push(self.scopes[contains as usize][&argument].clone());
-                                } else {
-                                    println!("'{}' not found", argument);
-                                    return;
+                                OpCode::Or => {
+                                    *left_value || *right_value
                                 }
-                            } else {
-                                if self.scopes[current_scope].contains_key(&argument) {
-                                    self.stack.push(self.scopes[current_scope][&argument].clone());
-                                } else {
-                                    let mut contains = false;
-                                    let mut index = 0;
-                                    let mut parent: (usize, String) = (0, "".to_string());
-                                    let mut has_parent = false;
-                                    for (i, j) in &used_variables {
-                                        if *j == argument {
-                                            index = i.clone();
-                                            contains = true;
-                                        }
-                                        if *i == 2137 {
-                                            has_parent = true;
-                                            parent = (*i, j.clone())
-                                        }
-                                    }
-
-                                    let mut found = false;
-                                    if has_parent {
-                                        for scope in &self.scopes {
-                                            if scope.contains_key(&*parent.1) {
-                                                if scope[&*parent.1].members.contains_key(&*argument) {
-                                                    if let Variable::YapkoObject(
-                                                        mut yapko
-                                                    ) = scope[&*parent.1].members[&*argument].clone() {
-                                                        yapko.parent = parent.1.clone();
-                                                        self.stack.push(yapko);
-                                                        found = true;
-                                                    } else {
-                                                        todo!()
-                                                    };
-                                                }
-                                            }
-                                        }
-                                    }
-                                    if !found {
-                                        if contains {
-                                            self.stack.push(self.scopes[index][&argument].clone());
-                                        } else {
-                                            println!("'{}' not found", argument);
-                                            return;
-                                        }
-                                    }
+                                _ => {
+                                    *left_value ^ *right_value
                                 }
-                            }
-                        }
-                        "set_get" => {
-                            if self.scopes[current_scope].contains_key(&*argument) {
-                                println!("{} was already defined", &*argument);
-                                return;
-                            } else {
-                                self.scopes[current_scope].insert(argument.to_string(), generate_null(argument.to_string()));
-                                self.stack.push(self.scopes[current_scope][&argument].clone());
-                            }
-                        }
-                        "call" => {
-                            let index = if arguments.len() < 1 {
-                                self.stack.len()-1
-                            } else {
-                                self.stack.len()-1-arguments[0] as usize
                             };
-                            let a = self.stack[index].clone();
-                            self.stack.remove(index);
-                            if a.yapko_type == "Function" || a.yapko_type == "YapkoFunction" {
-                                match &a.members["value"] {
-                                    Variable::Primitive(Function(..)) => {
-                                        if let Variable::Primitive(
-                                            Function(function)
-                                        ) = a.members["value"] {
-                                            function(&mut self.stack);
-                                        } else {
-                                            println!("Cannot invoke '{}'", a.name);
-                                            return;
-                                        };
-                                    }
-                                    Variable::Primitive(YapkoFunction(..)) => {
-                                        if let Variable::Primitive(
-                                            YapkoFunction(
-                                                function_bytecode,
-                                                this_used_variables
-                                            )
-                                        ) = a.members["value"].clone() {
-                                            let mut index = i + 2;
-                                            // Insert scope_new (val: 24)
-                                            bytecode.insert(i, 24);
-
-                                            // Insert everything inside function
-                                            bytecode.insert(i + 1, 0);
-                                            for byte2 in function_bytecode {
-                                                bytecode.insert(index, byte2);
-                                                index += 1;
-                                            }
-
-                                            // Insert scope_end (val: 25)
-                                            bytecode.insert(index, 25);
-                                            bytecode.insert(index+1, 0);
-
-                                            // Set function's scope
-                                            used_variables.append(&mut this_used_variables.clone());
-                                            for scope in &self.scopes {
-                                                if scope.contains_key(&a.parent) {
-                                                    used_variables.append(
-                                                        &mut vec![(2137, a.parent.clone())]
-                                                    );
-                                                }
-                                            }
-
-                                            invoke_started_at_scope = current_scope;
-                                        } else {
-                                            println!("Cannot invoke '{}'", a.name);
-                                            return;
-                                        };
-                                    }
-                                    _ => {
-                                    }
-                                }
-                            } else {
-                                if a.yapko_type == "class" {
-                                    // Create variable with default parameters
-                                    let mut new_a = a.clone();
-                                    new_a.yapko_type = a.name.clone();
-                                    self.stack.push(new_a.clone());
-                                } else {
-                                    println!("Cannot invoke '{}'", a.name);
-                                    return;
-                                }
-                            }
+                            self.stack.push(Value::Boolean(result));
                         }
-                        "+"|"-"|"*"|"/"|"<"|">"|"<="|">="|"=="|"!="|"%" => {
-                            let function_name= operator_to_function_name(
-                                commands[&command].clone()
-                            );
-
-                            let a = self.stack[&self.stack.len()-2].clone();
-                            if a.members.contains_key(&*function_name) {
-                                if let Variable::YapkoObject(
-                                    yapko_function
-                                ) = a.members[function_name].clone() {
-                                    if let Variable::Primitive(
-                                        Function(function)
-                                    ) = yapko_function.members["value"] {
-                                        function(&mut self.stack);
-                                    }
-                                } else {
-                                    println!(
-                                        "Variable {} ({}) does not implement function '{}'",
-                                        a.name, a.yapko_type, function_name
-                                    );
-                                }
-                            }
+                        _ => {
+                            return Err(YapkoError::new(
+                                ErrorKind::TypeError,
+                                format!(
+                                    "Expected Boolean {} Boolean, but got {} {} {}",
+                                    opcode, left.yapko_type(), opcode, right.yapko_type()
+                                )
+                            ).at(instruction_start));
                         }
-                        "and"|"or"|"xor" => {
-                            let left = self.stack[&self.stack.len()-2].clone();
-                            let right = self.stack[&self.stack.len()-1].clone();
-                            if let Variable::Primitive(Boolean(left_value)) = left.members["value"] {
-                                if let Variable::Primitive(Boolean(right_value)) = right.members["value"] {
-                                    let result = match commands[&command].as_str() {
-                                        "and" => {
-                                            left_value && right_value
-                                        }
-                                        "or"  => {
-                                            left_value || right_value
-                                        }
-                                        "xor" => {
-                                            left_value ^ right_value
-                                        }
-                                        _ => {
-                                            process::exit(1);
-                                        }
-                                    };
-
-                                    self.stack.remove(&self.stack.len()-1);
-                                    self.stack.remove(&self.stack.len()-1);
-                                    self.stack.push(generate_boolean(String::from("$bool"), result));
-                                }
-                            } else {
-                                println!("Error")
-                            }
+                    }
+                }
+                OpCode::Member => {
+                    let object = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+
+                    match self.lookup(&object, argument) {
+                        Some(member) => self.stack.push(bind_member(member, &object)),
+                        None => {
+                            return Err(YapkoError::new(
+                                ErrorKind::NameError,
+                                format!("{} does not implement {}", object.yapko_type(), argument)
+                            ).at(instruction_start));
                         }
-                        "." => {
-                            let left = self.stack[&self.stack.len()-1].clone();
-
-                            if left.members.contains_key(&*argument) {
-                                if let Variable::YapkoObject(
-                                    mut variable
-                                ) = left.members[&argument].clone() {
-                                    if variable.yapko_type != "Function" {
-                                        self.stack.remove(&self.stack.len()-1);
-                                    }
-
-                                    variable.parent = left.name;
-                                    self.stack.push(variable);
-                                }
-                            } else {
-                                println!("Variable {} does not implement {}", left.name, argument);
-                                process::exit(1);
-                            }
+                    }
+                }
+                OpCode::Not => {
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    if let Value::Boolean(boolean) = value {
+                        self.stack.push(Value::Boolean(!boolean));
+                    } else if let Some(function) = self.lookup(&value, "not").as_ref().and_then(user_function) {
+                        i = self.enter(function, Some(value), CallKind::Method, 0, i, &mut current_scope)
+                            .map_err(|error| error.at(instruction_start))?;
+                    } else {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!("Expected Boolean, but got {}", value.yapko_type())
+                        ).at(instruction_start));
+                    }
+                }
+                OpCode::FunStart => {
+                    let function = generate_yapko_function(
+                        argument.to_string(),
+                        self.code.clone(),
+                        i,
+                        code.read_u8(operand + CONSTANT_SIZE) as usize,
+                        self.environment(current_scope)
+                    );
+                    self.scopes[current_scope].borrow_mut().insert(argument.to_string(), function);
+
+                    // Skip the body
+                    i += code.read_u32(operand + CONSTANT_SIZE + 1);
+                }
+                OpCode::Return => {
+                    let value = if code.read_u8(operand) == 0 {
+                        Value::Null
+                    } else {
+                        pop(&mut self.stack).map_err(|error| error.at(instruction_start))?
+                    };
+
+                    // Leave files executed inside of the function
+                    while self.frames.last().is_some_and(|frame| frame.executed_file) {
+                        self.frames.pop();
+                    }
+
+                    match self.frames.pop() {
+                        Some(frame) => {
+                            i = self.leave(frame, Some(value), &mut current_scope);
+                        }
+                        None => {
+                            return Err(YapkoError::new(
+                                ErrorKind::RuntimeError,
+                                String::from("'return' outside of a function")
+                            ).at(instruction_start));
                         }
-                        "!" => {
-                            let left = self.stack[&self.stack.len()-1].clone();
-                            if let Variable::Primitive(Boolean(boolean)) = left.members["value"] {
-                                self.stack.remove(&self.stack.len()-1);
-                                self.stack.push(generate_boolean(String::from("$bool"), !boolean));
+                    }
+                }
+                OpCode::FunEnd => {
+                    // Function without `return` returns null
+                    if let Some(frame) = self.frames.pop() {
+                        i = self.leave(frame, Some(Value::Null), &mut current_scope);
+                    }
+                }
+                OpCode::ScopeNew => {
+                    new_scope(&mut self.scopes, &mut current_scope);
+                }
+                OpCode::ScopeEnd => {
+                    self.end_scope(&mut current_scope);
+                }
+                OpCode::Arg => {
+                    current_function_argument = argument.to_string();
+                }
+                OpCode::ArgType => {
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    if !is_instance_of(&value, argument) {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!("Expected {}, but got {}", argument, value.yapko_type())
+                        ).at(instruction_start));
+                    }
+                    self.scopes[current_scope].borrow_mut().insert(current_function_argument.clone(), value);
+                }
+
+                OpCode::Jump => {
+                    i += code.read_u32(operand);
+                }
+                OpCode::JumpIfFalse => {
+                    match pop(&mut self.stack).map_err(|error| error.at(instruction_start))? {
+                        Value::Boolean(boolean) => {
+                            if !boolean {
+                                i += code.read_u32(operand);
                             }
                         }
-                        "fun_start" => {
-                            self.inside_function = true;
-                            function_name = argument.clone();
+                        condition => {
+                            return Err(YapkoError::new(
+                                ErrorKind::TypeError,
+                                format!("Expected Boolean, but got {}", condition.yapko_type())
+                            ).at(instruction_start));
                         }
-                        "scope_new" => {
-                            new_scope(&mut self.scopes, &mut current_scope);
+                    }
+                }
+                OpCode::Loop => {
+                    i -= code.read_u32(operand);
+                }
+                OpCode::Range => {
+                    let end = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    let start = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    match (&start, &end) {
+                        (Value::Int(start), Value::Int(end)) => {
+                            self.stack.push(generate_range(*start, *end));
                         }
-                        "scope_end" => {
-                            end_scope(&mut self.scopes, &mut current_scope, invoke_started_at_scope.clone(), &mut used_variables);
+                        _ => {
+                            return Err(YapkoError::new(
+                                ErrorKind::TypeError,
+                                format!("Expected Int..Int, but got {}..{}", start.yapko_type(), end.yapko_type())
+                            ).at(instruction_start));
                         }
-                        "arg" => {
-                            current_function_argument = argument.clone();
+                    }
+                }
+                OpCode::ForNext => {
+                    let value = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    if matches!(value, Value::Null) {
+                        i += code.read_u32(operand + CONSTANT_SIZE);
+                    } else {
+                        // Loop variable is replaced on every iteration
+                        self.scopes[current_scope].borrow_mut().insert(argument.to_string(), value);
+                    }
+                }
+
+                OpCode::Class => {
+                    new_scope(&mut self.scopes, &mut current_scope);
+                    class_names.push(argument.to_string());
+                }
+
+                OpCode::Inherit => {
+                    let superclass = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    if superclass.yapko_type() != "class" {
+                        return Err(YapkoError::new(
+                            ErrorKind::TypeError,
+                            format!("Cannot inherit from {}", superclass.yapko_type())
+                        ).at(instruction_start));
+                    }
+                    // Becomes a member of the class like the fields and methods defined in its body
+                    self.scopes[current_scope].borrow_mut().insert(String::from("$super"), superclass);
+                }
+
+                OpCode::Super => {
+                    let superclass = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    let receiver = pop(&mut self.stack).map_err(|error| error.at(instruction_start))?;
+                    let (name, member) = match &superclass {
+                        Value::Object(class) => {
+                            let class = class.borrow();
+                            (class.name.clone(), class.members.get(argument).cloned())
                         }
-                        "arg_type" => {
-                            if self.stack[&self.stack.len()-1].yapko_type != argument {
-                                println!(
-                                    "Expected {}, but got {}",
-                                    argument,
-                                    self.stack[&self.stack.len()-1].yapko_type,
-                                );
-                                process::exit(1);
-                            }
-                            self.scopes[current_scope].insert(
-                                current_function_argument.clone(),
-                                self.stack[&self.stack.len()-1].clone()
-                            );
-                            self.stack.remove(&self.stack.len()-1);
+                        _ => (superclass.yapko_type(), None)
+                    };
+                    match member {
+                        Some(member) => self.stack.push(bind_member(member, &receiver)),
+                        None => {
+                            return Err(YapkoError::new(
+                                ErrorKind::NameError,
+                                format!("Class {} does not implement {}", name, argument)
+                            ).at(instruction_start));
                         }
-                        "if" => {
-                            let condition = self.stack[&self.stack.len()-1].clone();
-                            if let Variable::Primitive(Boolean(boolean)) = condition.members["value"] {
-                                if !boolean {
-                                    inside_if = true;
-                                    if_opened_at = current_scope;
-                                }
-                                new_scope(&mut self.scopes, &mut current_scope);
+                    }
+                }
+
+                OpCode::Close => {
+                    let class_name = class_names.pop().unwrap_or_default();
+                    let mut members = std::mem::take(&mut *self.scopes[current_scope].borrow_mut());
+                    // Members of the superclass (and so of its own superclasses) which are not overridden, its field
+                    // initializers are run separately for every instance
+                    if let Some(Value::Object(superclass)) = members.get("$super").cloned() {
+                        for (name, member) in &superclass.borrow().members {
+                            if name != "$fields" {
+                                members.entry(name.clone()).or_insert_with(|| member.clone());
                             }
                         }
+                    }
+                    self.scopes[current_scope-1].borrow_mut().insert(class_name.clone(), Value::new_object(YapkoObject {
+                        name: class_name,
+                        yapko_type: String::from("class"),
+                        value: Primitive::None,
+                        members
+                    }));
 
-                        "condition" => {
-                            inside_loop = true;
-                            loop_map.remove(&current_scope);
-                            loop_map.insert(current_scope, vec![]);
-                            loop_started_at = current_scope;
-                            loop_current = current_scope;
-                        }
+                    self.end_scope(&mut current_scope);
+                }
 
-                        "while" => {
-                            let condition = self.stack[&self.stack.len()-1].clone();
-                            if let Variable::Primitive(
-                                Boolean(boolean)
-                            ) = condition.members["value"] {
-                                if !boolean {
-                                    loop_map.remove(&current_scope);
-                                    inside_if = true;
-                                    if_opened_at = current_scope;
-                                }
-                                new_scope(&mut self.scopes, &mut current_scope);
-                            }
-                        }
+                OpCode::Execute => {
+                    // Compile file
+                    let filename = format!("{}.yapko", argument);
+                    let file_content = fs::read_to_string(&filename).map_err(|error| YapkoError::new(
+                        ErrorKind::IOError,
+                        format!("Cannot open {}: {}", filename, error)
+                    ).at(instruction_start))?;
+                    let compiled_code = compile(file_content.clone(), &filename, &mut ByteCode::new()).map_err(
+                        |errors| YapkoError::new(
+                            ErrorKind::SyntaxError,
+                            errors.iter().map(|error| error.render(&file_content)).collect::<Vec<_>>().join("\n")
+                        ).at(instruction_start)
+                    )?;
 
-                        "class" => {
-                            new_scope(&mut self.scopes, &mut current_scope);
-                            inside_class = true;
-                            class_name = argument.clone();
-                        }
+                    // Run it with the same variables visible and come back here when it ends
+                    let (base_scope, environment) = match self.frames.last() {
+                        Some(frame) => (frame.base_scope, frame.environment.clone()),
+                        None => (0, Rc::from([]))
+                    };
+                    self.frames.push(Frame {
+                        return_code: self.code.clone(),
+                        return_address: i,
+                        base_scope,
+                        environment,
+                        stack_base: self.stack.len(),
+                        kind: CallKind::Function,
+                        executed_file: true,
+                    });
+                    self.code = Rc::new(compiled_code);
+                    i = 0;
+                }
 
-                        "close" => {
-                            if inside_class {
-                                let mut hashmap = HashMap::new();
-                                for (name, yapko_object) in &self.scopes[current_scope] {
-                                    let mut new_yapko_object = yapko_object.clone();
-                                    new_yapko_object.parent = class_name.clone();
-                                    hashmap.insert(
-                                        name.to_string(),
-                                        Variable::YapkoObject(new_yapko_object)
-                                    );
-                                }
-                                self.scopes[current_scope-1].insert(class_name.clone(), YapkoObject {
-                                    name: class_name.clone(),
-                                    parent: "".to_string(),
-                                    yapko_type: "class".to_string(),
-                                    members: hashmap
-                                });
-
-                                inside_class = false;
-                            }
+                OpCode::Nop => {}
+            }
+        }
+        Ok(())
+    }
+}
 
-                            end_scope(&mut self.scopes, &mut current_scope, invoke_started_at_scope.clone(), &mut used_variables);
-                            if loop_map.contains_key(&current_scope) {
-                                let mut index = i;
-                                for byte2 in &loop_map[&current_scope] {
-                                    bytecode.insert(index, *byte2);
-                                    index += 1;
-                                }
-                            }
-                        }
+// Code, start of the body, number of parameters and environment of a function defined in Yapko code
+type UserFunction = (Rc<Chunk>, usize, usize, Rc<[Scope]>);
 
-                        "execute" => {
-                            // Compile file
-                            let code = get_file_content(&format!("{}.yapko", &argument));
-                            let compiled_code = compile(code, &mut ByteCode::new());
+// Code of a function defined in Yapko code, None for any other value
+fn user_function(value: &Value) -> Option<UserFunction> {
+    match value {
+        Value::Object(function) => match &function.borrow().value {
+            Primitive::YapkoFunction(function_code, start, parameter_count, environment) => {
+                Some((Rc::clone(function_code), *start, *parameter_count, Rc::clone(environment)))
+            }
+            _ => None
+        },
+        _ => None
+    }
+}
 
-                            // Paste bytecode
-                            let mut index = i;
-                            for byte in compiled_code {
-                                bytecode.insert(index, byte);
-                                index += 1;
-                            }
-                            bytecode.insert(index, 0);
-                        }
+// Function implemented in Rust, None for any other value
+fn native_function(value: &Value) -> Option<NativeFunction> {
+    match value {
+        Value::Object(function) => match function.borrow().value {
+            Primitive::Function(function) => Some(function),
+            _ => None
+        },
+        _ => None
+    }
+}
 
-                        _ => {}
-                    }
+// Value is an instance of a user class
+fn is_instance(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => {
+            let object = object.borrow();
+            matches!(object.value, Primitive::None) && object.yapko_type != "class"
+        }
+        _ => false
+    }
+}
 
-                    new_command = true;
-                    argument.clear();
-                    arguments.clear();
-                } else if !self.inside_function && !inside_loop {
-                    if inside_if {
-                        if byte == 0 {
-                            new_command = true;
-                        }
-                        if commands[&command] == "close" {
-                            argument.clear();
-                            end_scope(&mut self.scopes, &mut current_scope, invoke_started_at_scope.clone(), &mut used_variables);
-                            if if_opened_at == current_scope {
-                                inside_if = false;
-                                continue;
-                            }
-                        } else if commands[&command] == "if" || commands[&command] == "while" {
-                            current_scope+=1;
-                        }
-                    }
-                    arguments.push(byte);
-                    argument.push(byte as char);
-                } else if self.inside_function {
-                    if byte == 0 {
-                        new_command = true;
-                    }
-                    if commands[&command] == String::from("fun_end") {
-                        if byte == 0 {
-                            // Create function outside scope
-                            let mut used_variables:Vec<(usize, String)> = Vec::new();
-                            for i in (0..=current_scope).rev() {
-                                for map in &self.scopes[i] {
-                                    // Check if used_variables already contain variable with the same name
-                                    let mut contains = false;
-                                    for j in 0..used_variables.len() {
-                                        if self.scopes[used_variables[j].0][&used_variables[j].1].name == map.0.clone() {
-                                            contains = true
-                                        }
-                                    }
-
-                                    // If it does not contain the variable add it to scope
-                                    if !contains {
-                                        used_variables.push((i, map.0.clone()));
-                                    }
-                                }
-                            }
+// Value is of the type or of one of its subclasses
+fn is_instance_of(value: &Value, type_name: &str) -> bool {
+    if value.yapko_type() == type_name {
+        return true;
+    }
+    let Value::Object(object) = value else {
+        return false;
+    };
+    // Classes have the superclass as a member too, but they are not instances of it
+    if object.borrow().yapko_type == "class" {
+        return false;
+    }
+    let mut superclass = object.borrow().members.get("$super").cloned();
+    while let Some(Value::Object(class)) = superclass {
+        if class.borrow().name == type_name {
+            return true;
+        }
+        superclass = class.borrow().members.get("$super").cloned();
+    }
+    false
+}
 
-                            self.inside_function = false;
-                            self.scopes[current_scope].insert(
-                                argument.clone(),
-                                generate_yapko_function(
-                                    argument.clone(),
-                                    bytecode_of_function.clone(),
-                                    used_variables,
-                                    current_scope
-                                )
-                            );
-                            bytecode_of_function.clear();
-                            argument.clear();
-                        } else {
-                            argument.push(byte as char);
-                        }
-                    } else {
-                        bytecode_of_function.push(byte);
-                    }
-                } else if inside_loop {
-                    if byte == 0 {
-                        if loop_map.contains_key(&loop_started_at) {
-                            let mut current_bytecode = loop_map[&loop_started_at].clone();
-                            current_bytecode.push(command);
-                            current_bytecode.append(&mut arguments);
-                            current_bytecode.push(0);
-
-                            loop_map.remove(&loop_started_at);
-                            loop_map.insert(loop_started_at, current_bytecode);
-                        }
-                        match commands[&command].as_str() {
-                            "close" => {
-                                loop_current -= 1;
-                                if loop_current == loop_started_at {
-                                    let mut index = i;
-                                    for byte2 in &loop_map[&loop_started_at] {
-                                        bytecode.insert(index, *byte2);
-                                        index += 1;
-                                    }
-                                    inside_loop = false;
-                                }
-                            }
-                            "while"|"if" => {
-                                loop_current += 1;
-                            }
-                            _ => {}
-                        }
+// Member of an object as seen through it: methods are called with the object as `self`
+fn bind_member(member: Value, object: &Value) -> Value {
+    let value = if let Some((function_code, start, parameter_count, environment)) = user_function(&member) {
+        Primitive::YapkoFunction(function_code, start, parameter_count, environment)
+    } else if let Some(function) = native_function(&member) {
+        Primitive::Function(function)
+    } else {
+        return member;
+    };
+    let Value::Object(function) = &member else {
+        return member;
+    };
+    let function = function.borrow();
+    Value::new_object(YapkoObject {
+        name: function.name.clone(),
+        yapko_type: function.yapko_type.clone(),
+        value,
+        members: HashMap::from([(String::from("self"), object.clone())]),
+    })
+}
 
-                        new_command = true;
-                    } else {
-                        arguments.push(byte);
-                    }
-                }
+// Call function implemented in Rust with only its own arguments on the stack, returns null if it returns nothing
+fn call_native(function: NativeFunction, mut arguments: Vec<Value>) -> Result<Value, YapkoError> {
+    function(&mut arguments)?;
+    Ok(arguments.pop().unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use crate::yapko::{generate_function, generate_standard, WeakScope};
+
+    thread_local! {
+        // Innermost scope of every function passed to keep()
+        static KEPT: RefCell<Vec<WeakScope>> = const { RefCell::new(vec![]) };
+    }
+
+    // Replaces everything on its stack with the number of values that were there
+    fn argument_count(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let count = stack.len() as i32;
+        stack.clear();
+        stack.push(Value::Int(count));
+        Ok(())
+    }
+
+    // Remembers the scope the function was defined in without keeping it alive
+    fn keep(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        if let Some((_, _, _, environment)) = stack.pop().as_ref().and_then(user_function) {
+            KEPT.with(|kept| kept.borrow_mut().push(Rc::downgrade(environment.last().unwrap())));
+        }
+        Ok(())
+    }
+
+    // Which of the scopes passed to keep() so far are still alive
+    fn kept_alive() -> Vec<bool> {
+        KEPT.with(|kept| kept.borrow().iter().map(|scope| scope.upgrade().is_some()).collect())
+    }
+
+    // Run code with the standard library, returns the VM or the error it stopped with
+    fn run(code: &str) -> Result<VM, String> {
+        let chunk = compile(code.to_string(), "test.yapko", &mut ByteCode::new())
+            .map_err(|errors| errors[0].message.clone())?;
+        KEPT.with(|kept| kept.borrow_mut().clear());
+        let mut vm = VM::new();
+        vm.scopes[0].replace(generate_standard());
+        vm.scopes[0].borrow_mut().insert(
+            String::from("argumentCount"),
+            generate_function(String::from("argumentCount"), argument_count)
+        );
+        vm.scopes[0].borrow_mut().insert(String::from("keep"), generate_function(String::from("keep"), keep));
+        vm.interpret(chunk).map_err(|error| format!("{}: {}", error.kind, error.message))?;
+        Ok(vm)
+    }
+
+    // Global variables after running the code, rendered as text
+    fn globals(code: &str, names: &[&str]) -> Result<Vec<String>, String> {
+        let vm = run(code)?;
+        Ok(names.iter().map(|name| render(&vm.scopes[0].borrow()[*name])).collect())
+    }
+
+    fn render(value: &Value) -> String {
+        match value {
+            Value::Int(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::Boolean(value) => value.to_string(),
+            Value::Null => String::from("null"),
+            Value::Object(object) => match &object.borrow().value {
+                Primitive::YapkoString(string) => format!("{:?}", string),
+                _ => object.borrow().yapko_type.clone()
             }
         }
     }
+
+    #[test]
+    fn unused_results_are_discarded() {
+        let vm = run(
+            "function g() {\n return 1\n}\nlet i = 0\nwhile i < 100 {\n g()\n i = i + 1\n}\nlet n = argumentCount()"
+        ).unwrap();
+        assert!(vm.stack.is_empty());
+        assert_eq!(render(&vm.scopes[0].borrow()["n"]), "0");
+
+        // Methods and natives used as statements
+        let vm = run(
+            "class C {\n let n = 0\n function inc() {\n  n = n + 1\n  return n\n }\n}\nlet c = C()\n\
+             c.inc()\nc.inc()\n\"abc\".toInt\nfunction f() {\n}\nf()\nlet n = argumentCount()"
+        ).unwrap();
+        assert!(vm.stack.is_empty());
+        assert_eq!(render(&vm.scopes[0].borrow()["n"]), "0");
+    }
+
+    #[test]
+    fn loops_and_branches_jump_within_the_code() {
+        assert_eq!(
+            globals(
+                "let i = 0\nlet even = 0\nwhile i < 10000 {\n i = i + 1\n if i % 2 == 0 {\n  even = even + 1\n }\n}",
+                &["i", "even"]
+            ),
+            Ok(vec![String::from("10000"), String::from("5000")])
+        );
+
+        assert_eq!(
+            globals(
+                "let a = 0\nlet b = 0\nwhile a < 10 {\n a = a + 1\n if a < 5 {\n  continue\n }\n if a == 7 {\n  \
+                 break\n }\n b = b + a\n}",
+                &["a", "b"]
+            ),
+            Ok(vec![String::from("7"), String::from("11")])
+        );
+    }
+
+    #[test]
+    fn every_call_returns_a_value() {
+        assert_eq!(
+            globals(
+                "function f() {\n}\nfunction g() {\n return\n}\nlet a = f()\nlet b = g()\nlet c = printLine(\"\")",
+                &["a", "b", "c"]
+            ),
+            Ok(vec![String::from("null"), String::from("null"), String::from("null")])
+        );
+    }
+    #[test]
+    fn assignment_is_an_expression() {
+        assert_eq!(
+            globals("let a = 0\nlet b = 0\n7\na = b = 1\nlet c = (a = 5) + 1", &["a", "b", "c"]),
+            Ok(vec![String::from("5"), String::from("1"), String::from("6")])
+        );
+        assert_eq!(
+            globals("class P {\n let x = 0\n}\nlet p = P()\nlet y = 0\nlet z = p.x = y = 3\nlet x = p.x", &["x", "y", "z"]),
+            Ok(vec![String::from("3"), String::from("3"), String::from("3")])
+        );
+        assert!(run("let a = 0\nprintLine(a = 5)").unwrap().stack.is_empty());
+    }
+    #[test]
+    fn field_initializers_run_for_every_instance() {
+        assert_eq!(
+            globals(
+                "class Box {\n let v = 0\n}\nclass Holder {\n let inner = Box()\n let same = inner\n}\n\
+                 let h1 = Holder()\nlet h2 = Holder()\nh1.inner.v = 42\nlet a = h1.inner.v\nlet b = h2.inner.v\n\
+                 let c = h1.same.v",
+                &["a", "b", "c"]
+            ),
+            Ok(vec![String::from("42"), String::from("0"), String::from("42")])
+        );
+        // Initializer referring to an existing object shares it
+        assert_eq!(
+            globals(
+                "class Reg {\n let n = 0\n}\nlet shared = Reg()\nclass A {\n let r = shared\n}\nA().r.n = 5\n\
+                 let a = shared.n",
+                &["a"]
+            ),
+            Ok(vec![String::from("5")])
+        );
+        // Fields of the superclass are set first, then the ones of the subclass, then init runs
+        assert_eq!(
+            globals(
+                "class A {\n let x = 1\n}\nclass B : A {\n let y = x + 1\n function init(z: Int) {\n  \
+                 y = y + z\n }\n}\nclass C : B {\n}\nlet c = C(10)\nlet x = c.x\nlet y = c.y",
+                &["x", "y"]
+            ),
+            Ok(vec![String::from("1"), String::from("12")])
+        );
+        assert_eq!(
+            run("class A {\n let n = missing\n}\nA()").err(),
+            Some(String::from("NameError: 'missing' not found"))
+        );
+    }
+
+    #[test]
+    fn functions_see_the_scopes_they_were_defined_in() {
+        // Locals of the caller are not visible, even in a block at the index where the class was defined
+        assert_eq!(
+            run("class A {\n function f() {\n  return secret\n }\n}\nlet a = A()\nlet i = 0\nwhile i < 1 {\n \
+                 let secret = 5\n printLine(a.f())\n i = i + 1\n}").err(),
+            Some(String::from("NameError: 'secret' not found"))
+        );
+        assert_eq!(
+            globals(
+                "let base = 10\nfunction outer(n: Int) {\n let local = n\n function inner(m: Int) {\n  \
+                 return base + local + m\n }\n return inner\n}\nlet f = outer(1)\nlet a = f(100)\n\
+                 function count(n: Int) {\n if n == 0 {\n  return 0\n }\n return 1 + count(n - 1)\n}\n\
+                 let b = count(5)\nfunction bump() {\n base = base + 1\n}\nbump()\nlet c = base",
+                &["a", "b", "c"]
+            ),
+            Ok(vec![String::from("111"), String::from("5"), String::from("11")])
+        );
+    }
+    #[test]
+    fn scopes_are_freed_once_only_their_functions_refer_to_them() {
+        // Scope of a call and of a loop body, each holding a function defined in it
+        let vm = run(
+            "function outer(n: Int) {\n function helper() {\n  return n\n }\n keep(helper)\n return helper()\n}\n\
+             let a = outer(1)\nlet i = 0\nwhile i < 3 {\n function f() {\n  return i\n }\n keep(f)\n i = i + 1\n}"
+        ).unwrap();
+        assert_eq!(kept_alive(), vec![false; 4]);
+        assert_eq!(render(&vm.scopes[0].borrow()["a"]), "1");
+
+        // Returned function keeps its scope until it is replaced
+        let vm = run(
+            "function make(n: Int) {\n function get() {\n  return n\n }\n keep(get)\n return get\n}\n\
+             let g = make(0)\nlet i = 1\nwhile i < 100 {\n g = make(i)\n i = i + 1\n}\nlet a = g()"
+        ).unwrap();
+        let alive = kept_alive();
+        assert!(!alive[0]);
+        assert!(alive[99]);
+        assert_eq!(render(&vm.scopes[0].borrow()["a"]), "99");
+    }
+
+    #[test]
+    fn integer_overflow_is_an_error() {
+        let overflow = |code: &str| run(code).err();
+        assert_eq!(
+            overflow("let a = 2147483647 + 1"),
+            Some(String::from("ValueError: Cannot compute 2147483647 + 1, result does not fit into Int"))
+        );
+        assert_eq!(
+            overflow("let a = (-2147483647 - 1) / -1"),
+            Some(String::from("ValueError: Cannot compute -2147483648 / -1, result does not fit into Int"))
+        );
+        assert!(overflow("let a = (-2147483647 - 1) % -1").is_some());
+        assert!(overflow("let a = -2147483647 - 2").is_some());
+        assert!(overflow("let a = 65536 * 65536").is_some());
+        assert_eq!(
+            overflow("let a = 1 / 0"),
+            Some(String::from("DivisionByZero: Division by zero"))
+        );
+        assert_eq!(globals("let a = 2147483646 + 1", &["a"]), Ok(vec![String::from("2147483647")]));
+    }
+    #[test]
+    fn comparisons_of_floats_and_strings() {
+        assert_eq!(
+            globals(
+                "let a = 1.5 <= 1.5\nlet b = 1.5 >= 2.5\nlet c = 2.5 >= 1.5\nlet d = 1.5 != 1.5\nlet e = 0.1 != 0.2",
+                &["a", "b", "c", "d", "e"]
+            ),
+            Ok(vec![
+                String::from("true"), String::from("false"), String::from("true"), String::from("false"),
+                String::from("true")
+            ])
+        );
+        assert_eq!(
+            globals(
+                "let a = \"a\" <= \"b\"\nlet b = \"b\" <= \"a\"\nlet c = \"b\" >= \"b\"\nlet d = \"a\" >= \"ab\"\n\
+                 let e = \"a\" != \"b\"\nlet f = \"a\" != \"a\"",
+                &["a", "b", "c", "d", "e", "f"]
+            ),
+            Ok(vec![
+                String::from("true"), String::from("false"), String::from("true"), String::from("false"),
+                String::from("true"), String::from("false")
+            ])
+        );
+        assert_eq!(
+            run("let a = 1.5 <= 2").err(),
+            Some(String::from("TypeError: Float does not implement smallerOrEqual(Int)"))
+        );
+    }
+
+    #[test]
+    fn negation_of_numbers_and_user_classes() {
+        assert_eq!(
+            globals(
+                "let n = 3\nlet a = -n\nlet b = --n\nlet c = -1.5\nlet d = -(-2.5)\nlet e = +n",
+                &["a", "b", "c", "d", "e"]
+            ),
+            Ok(vec![
+                String::from("-3"), String::from("3"), String::from("-1.5"), String::from("2.5"), String::from("3")
+            ])
+        );
+        assert_eq!(globals("let m = -2147483648\nlet n = -(m + 1)", &["m", "n"]), Ok(vec![
+            String::from("-2147483648"), String::from("2147483647")
+        ]));
+        assert_eq!(
+            run("let m = -2147483648\nlet n = -m").err(),
+            Some(String::from("ValueError: Cannot negate -2147483648, result does not fit into Int"))
+        );
+
+        let class = "class V {\n let n = 0\n function init(n: Int) {\n  self.n = n\n }\n\
+                     function negate() {\n  return V(n * 2)\n }\n}\n";
+        assert_eq!(globals(&format!("{}let a = (-V(4)).n\nlet b = (--V(1)).n", class), &["a", "b"]), Ok(vec![
+            String::from("8"), String::from("4")
+        ]));
+        assert_eq!(
+            run("let a = -true").err(),
+            Some(String::from("TypeError: Boolean does not implement function 'negate'"))
+        );
+    }
+
+    #[test]
+    fn exactly_one_branch_runs() {
+        let code = "let log = 0\nfunction pick(n: Int) {\n if n < 0 {\n  log = log * 10 + 1\n } else if n == 0 {\n  \
+                    log = log * 10 + 2\n } else if n < 10 {\n  log = log * 10 + 3\n } else {\n  \
+                    log = log * 10 + 4\n }\n}\npick(-1)\npick(0)\npick(5)\npick(50)";
+        assert_eq!(globals(code, &["log"]), Ok(vec![String::from("1234")]));
+
+        // Conditions after the first true one are not evaluated, without else nothing runs
+        let code = "let checked = 0\nlet ran = 0\nfunction check(result: Boolean) {\n checked = checked + 1\n \
+                    return result\n}\nif check(false) {\n ran = 1\n} else if check(true) {\n ran = 2\n} else if \
+                    check(true) {\n ran = 3\n} else {\n ran = 4\n}\nif check(false) {\n ran = 5\n} else if \
+                    check(false) {\n ran = 6\n}";
+        assert_eq!(globals(code, &["checked", "ran"]), Ok(vec![String::from("4"), String::from("2")]));
+    }
+
+    #[test]
+    fn for_loops_over_strings_and_user_classes() {
+        let code = "let count = 0\nlet ls = 0\nlet last = \"\"\nfor c in \"h\u{e9}llo\" {\n count = count + 1\n \
+                    if c == \"l\" {\n  ls = ls + 1\n }\n last = c\n}\nfor c in \"\" {\n count = 100\n}";
+        assert_eq!(globals(code, &["count", "ls", "last"]), Ok(vec![
+            String::from("5"), String::from("2"), String::from("\"o\"")
+        ]));
+
+        // next() returns null once there is nothing left
+        let class = "class Down {\n let n = 0\n function init(n: Int) {\n  self.n = n\n }\n function iter() {\n  \
+                     return self\n }\n function next() {\n  if n == 0 {\n   return\n  }\n  n = n - 1\n  \
+                     return n\n }\n}\n";
+        assert_eq!(globals(&format!("{}let t = 0\nfor x in Down(4) {{\n t = t * 10 + x\n}}", class), &["t"]), Ok(vec![
+            String::from("3210")
+        ]));
+        assert_eq!(
+            run("class E {\n}\nfor x in E() {\n}").err(),
+            Some(String::from("NameError: E does not implement iter"))
+        );
+    }
+
+    #[test]
+    fn arguments_can_be_instances_of_subclasses() {
+        let classes = "class Animal {\n}\nclass Dog : Animal {\n}\nclass Puppy : Dog {\n}\n\
+                       function f(a: Animal) {\n return 1\n}\n";
+        assert_eq!(
+            globals(&format!("{}let a = f(Animal())\nlet b = f(Puppy())", classes), &["a", "b"]),
+            Ok(vec![String::from("1"), String::from("1")])
+        );
+        assert_eq!(
+            run(&format!("{}f(Dog)", classes)).err(),
+            Some(String::from("TypeError: Expected Animal, but got class"))
+        );
+        assert_eq!(
+            run(&format!("{}f(2)", classes)).err(),
+            Some(String::from("TypeError: Expected Animal, but got Int"))
+        );
+    }
+    #[test]
+    fn operators_dispatch_to_methods_of_either_operand() {
+        let class = "class V {\n let n = 0\n function init(n: Int) {\n  self.n = n\n }\n\
+                     function sub(other: Int) {\n  return n - other\n }\n function subFrom(other: Int) {\n  \
+                     return other - n\n }\n function divFrom(other: Int) {\n  return other / n\n }\n\
+                     function greaterThan(other: Int) {\n  return n > other\n }\n}\nlet v = V(4)\n";
+        assert_eq!(
+            globals(
+                &format!("{}let a = v - 1\nlet b = 10 - v\nlet c = 20 / v\nlet d = 1 < v", class),
+                &["a", "b", "c", "d"]
+            ),
+            Ok(vec![String::from("3"), String::from("6"), String::from("5"), String::from("true")])
+        );
+        assert_eq!(
+            run(&format!("{}let e = 1 % v", class)).err(),
+            Some(String::from("TypeError: Int does not implement mod(V) and V does not implement modFrom(Int)"))
+        );
+        let digits = "class D {\n let n = 0\n function init(n: Int) {\n  self.n = n\n }\n\
+                      function add(other: Int) {\n  return n * 10 + other\n }\n function addFrom(other: Int) {\n  \
+                      return other * 10 + n\n }\n function mul(other: Int) {\n  return n * 100 + other\n }\n}\n";
+        assert_eq!(
+            globals(&format!("{}let a = D(5) + 1\nlet b = 1 + D(5)\nlet c = D(5) * 1", digits), &["a", "b", "c"]),
+            Ok(vec![String::from("51"), String::from("15"), String::from("501")])
+        );
+        assert_eq!(
+            run(&format!("{}let d = 1 * D(5)", digits)).err(),
+            Some(String::from("TypeError: Int does not implement mul(D) and D does not implement mulFrom(Int)"))
+        );
+        assert_eq!(
+            run("let e = true < false").err(),
+            Some(String::from("TypeError: Neither Boolean nor Boolean implements function 'smallerThan'"))
+        );
+    }
+
+    #[test]
+    fn return_leaves_loops_and_blocks_of_the_call() {
+        let vm = run(
+            "function find(n: Int) {\n let i = 0\n while true {\n  for j in 0..10 {\n   if i * 10 + j == n {\n    \
+             return j\n   }\n  }\n  i = i + 1\n }\n}\nlet a = find(42)\nlet b = find(7)"
+        ).unwrap();
+        assert_eq!(render(&vm.scopes[0].borrow()["a"]), "2");
+        assert_eq!(render(&vm.scopes[0].borrow()["b"]), "7");
+        assert_eq!(vm.scopes.len(), 1);
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let error = |code: &str| run(code).err().unwrap_or_default();
+        assert_eq!(error("printLine(x)"), "NameError: 'x' not found");
+        assert_eq!(error("x = 1"), "NameError: 'x' not found");
+        assert_eq!(error("let x = 1\nlet x = 2"), "NameError: x was already defined");
+        assert_eq!(error("let x = 1\nx()"), "TypeError: Cannot invoke Int");
+        assert_eq!(error("class A {\n}\nA(1)"), "ArityError: A has no init, but got 1 arguments");
+        assert_eq!(error("let n = Int()"), "TypeError: Cannot create instances of Int");
+        assert_eq!(error("let io = IO()"), "TypeError: Cannot create instances of IO");
+        assert_eq!(error("class A {\n}\nlet a = A()\na.b = 1"), "NameError: A does not implement b");
+        assert_eq!(error("function f(n: Int) {\n}\nf()"), "ArityError: Expected 1 arguments, but got 0");
+        assert_eq!(error("function f() {\n}\nf(99)"), "ArityError: Expected 0 arguments, but got 1");
+        assert_eq!(
+            error("class A {\n function init(n: Int) {\n }\n}\nA()"),
+            "ArityError: Expected 1 arguments, but got 0"
+        );
+        assert_eq!(
+            error("class V {\n function add() {\n }\n}\nV() + 1"),
+            "ArityError: Expected 0 arguments, but got 1"
+        );
+        assert_eq!(error("function f(n: Int) {\n}\nf(\"a\")"), "TypeError: Expected Int, but got String");
+        assert_eq!(error("return 1"), "RuntimeError: 'return' outside of a function");
+        assert_eq!(error("if 1 {\n}"), "TypeError: Expected Boolean, but got Int");
+        assert_eq!(error("for x in 5 {\n}"), "NameError: Int does not implement iter");
+    }
+
+    #[test]
+    fn errors_point_at_the_failed_line_and_its_calls() {
+        let chunk = compile(
+            String::from("function f() {\n return missing\n}\nlet a = 1\nf()"),
+            "test.yapko",
+            &mut ByteCode::new()
+        ).unwrap();
+        let error = VM::new().interpret(chunk).err().unwrap();
+        assert_eq!(error.position.map(|position| position.line), Some(2));
+        assert_eq!(error.trace.iter().map(|position| position.line).collect::<Vec<_>>(), vec![5]);
+    }
+
+    #[test]
+    fn methods_change_the_fields_of_their_instance() {
+        let class = "class Counter {\n let count = 0\n function init(start: Int) {\n  count = start\n }\n\
+                     function inc() {\n  self.count = self.count + 1\n  return self\n }\n}\n";
+        assert_eq!(
+            globals(
+                &format!(
+                    "{}let c = Counter(5)\nc.inc().inc()\nlet d = Counter(0)\nd.inc()\nlet a = c.count\n\
+                     let b = d.count",
+                    class
+                ),
+                &["a", "b"]
+            ),
+            Ok(vec![String::from("7"), String::from("1")])
+        );
+    }
+
+    #[test]
+    fn super_calls_the_method_of_the_superclass() {
+        assert_eq!(
+            globals(
+                "class A {\n let n = 1\n function value() {\n  return n\n }\n}\nclass B : A {\n \
+                 function value() {\n  return super.value() + 10\n }\n}\nclass C : B {\n}\nlet a = C().value()",
+                &["a"]
+            ),
+            Ok(vec![String::from("11")])
+        );
+    }
+
+    #[test]
+    fn objects_are_shared_by_every_variable_and_argument() {
+        let class = "class Counter {\n let count = 0\n function init(start: Int) {\n  count = start\n }\n\
+                     function inc() {\n  self.count = self.count + 1\n  return self\n }\n}\n";
+        assert_eq!(
+            globals(
+                &format!(
+                    "{}let c = Counter(0)\nlet alias = c\nalias.inc()\nfunction twice(counter: Counter) {{\n \
+                     counter.inc()\n counter.inc()\n}}\ntwice(c)\nlet a = c.count",
+                    class
+                ),
+                &["a"]
+            ),
+            Ok(vec![String::from("3")])
+        );
+    }
 }
@@ -1,6 +1,8 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::process;
-use crate::yapko::Primitive::{Function, YapkoString};
+use std::rc::{Rc, Weak};
+use crate::bytecode::Chunk;
+use crate::error::{ErrorKind, YapkoError};
 use rand::Rng;
 
 macro_rules! hashmap {
@@ -11,55 +13,130 @@ macro_rules! hashmap {
     }}
 }
 
-fn execute_function(stack: &mut Vec<YapkoObject>, function_object: &YapkoObject) {
-    let value = &function_object.members[&String::from("value")];
-    if let Variable::Primitive(Primitive::Function(function)) = value {
-        function(stack);
+// Signature of functions implemented in Rust, methods get the object they were called on as their first argument
+pub type NativeFunction = fn(stack: &mut Vec<Value>) -> Result<(), YapkoError>;
+
+// Object on the heap, shared by every value referring to it
+pub type ObjectRef = Rc<RefCell<YapkoObject>>;
+
+// Variables of a block or a call, shared with the functions defined in it
+pub type Scope = Rc<RefCell<HashMap<String, Value>>>;
+
+// Reference to a scope which does not keep it alive
+pub type WeakScope = Weak<RefCell<HashMap<String, Value>>>;
+
+// Int, Float, Boolean and null are stored in place and copied, any other value refers to an object, so assigning
+// it or passing it to a function does not copy the object and changes to it are seen through every reference
+#[derive(Clone)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+    Boolean(bool),
+    Null,
+    Object(ObjectRef),
+}
+
+impl Value {
+    pub fn new_object(object: YapkoObject) -> Value {
+        Value::Object(Rc::new(RefCell::new(object)))
+    }
+
+    pub fn yapko_type(&self) -> String {
+        match self {
+            Value::Int(_) => String::from("Int"),
+            Value::Float(_) => String::from("Float"),
+            Value::Boolean(_) => String::from("Boolean"),
+            Value::Null => String::from("Null"),
+            Value::Object(object) => object.borrow().yapko_type.clone(),
+        }
     }
 }
 
+// Data of a built-in object besides its members
+pub enum Primitive {
+    YapkoString(String),
+    // Code containing the function, start of its body, number of parameters and scopes visible where it was defined,
+    // innermost last
+    YapkoFunction(Rc<Chunk>, usize, usize, Rc<[Scope]>),
+    Function(NativeFunction),
+    // Start (inclusive) and end (exclusive)
+    Range(i32, i32),
+    Iterator(Box<dyn Iterator<Item = Value>>),
+    // Built-in class, which only holds methods or functions and has no instances
+    NativeClass,
+    // Classes and their instances only have members
+    None,
+}
+
+pub struct YapkoObject {
+    // Name of a class or a function, empty for other objects
+    pub name: String,
+    pub yapko_type: String,
+    pub value: Primitive,
+    pub members: HashMap<String, Value>,
+}
+
+// Take value from the top of the stack
+pub fn pop(stack: &mut Vec<Value>) -> Result<Value, YapkoError> {
+    stack.pop().ok_or_else(|| YapkoError::new(
+        ErrorKind::RuntimeError,
+        String::from("Missing value on the stack")
+    ))
+}
+
+// Take value from the top of the stack and convert it to text, objects other than strings and ranges need a native
+// toString() member
+fn pop_text(stack: &mut Vec<Value>) -> Result<String, YapkoError> {
+    let value = pop(stack)?;
+    let object = match &value {
+        Value::Int(value) => return Ok(value.to_string()),
+        Value::Float(value) => return Ok(value.to_string()),
+        Value::Boolean(value) => return Ok(value.to_string()),
+        Value::Null => return Ok(String::from("null")),
+        Value::Object(object) => object.borrow(),
+    };
+    match &object.value {
+        Primitive::YapkoString(string) => return Ok(string.clone()),
+        Primitive::Range(start, end) => return Ok(format!("{}..{}", start, end)),
+        _ => {}
+    }
+
+    let to_string = match object.members.get("toString") {
+        Some(Value::Object(function)) => match function.borrow().value {
+            Primitive::Function(function) => function,
+            _ => return Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Error converting {} to String", object.yapko_type)
+            ))
+        },
+        _ => return Err(YapkoError::new(
+            ErrorKind::TypeError,
+            format!("Function toString() not found in {}", object.yapko_type)
+        ))
+    };
+    drop(object);
+
+    // toString function will push converted string to the stack
+    stack.push(value);
+    to_string(stack)?;
+    string_value(&pop(stack)?).ok_or_else(|| YapkoError::new(
+        ErrorKind::TypeError,
+        String::from("toString() did not return a String")
+    ))
+}
+
 // Generate standard library
-pub fn generate_standard() -> HashMap<String, YapkoObject> {
-    fn print_line(stack: &mut Vec<YapkoObject>) {
+#[allow(clippy::needless_return)]
+pub fn generate_standard() -> HashMap<String, Value> {
+    fn print_line(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
         // If there are no arguments - just write new line
-        if stack.len() == 0 {
+        if stack.is_empty() {
             println!();
-            return;
-        }
-
-        let value = stack[stack.len() - 1].clone();
-        if !value.members.contains_key("toString") {
-            // We do not need to convert string to string
-            if value.yapko_type == "String" {
-                let value = &value.members[&String::from("value")];
-                if let Variable::Primitive(YapkoString(string)) = value {
-                    println!("{}", string);
-                }
-                // Remove string from stack
-                stack.remove(stack.len() - 1);
-                return
-            } else {
-                println!("Function toString() not found in {}", value.yapko_type);
-                process::exit(1);
-            }
+            return Ok(());
         }
 
-        let to_string_object = &value.members[&String::from("toString")];
-        if let Variable::YapkoObject(yapko_function) = to_string_object {
-            // toString function will push converted string to the stack
-            execute_function(stack, yapko_function);
-            let string_object = &stack[stack.len() - 1];
-            let string_value = &string_object.members[&String::from("value")];
-            if let Variable::Primitive(YapkoString(text)) = string_value {
-                println!("{}", text);
-            } else {
-                println!("Error converting {} to String", value.name);
-            };
-            // Remove string from stack
-            stack.remove(stack.len() - 1);
-        } else {
-            println!("Error converting {} to String", value.name);
-        };
+        println!("{}", pop_text(stack)?);
+        Ok(())
     }
 
     let mut output = HashMap::new();
@@ -68,487 +145,567 @@ pub fn generate_standard() -> HashMap<String, YapkoObject> {
         generate_function(String::from("printLine"), print_line)
     );
 
-    fn print(stack: &mut Vec<YapkoObject>) {
-        let value = stack[stack.len() - 1].clone();
-        if !value.members.contains_key("toString") {
-            // We do not need to convert string to string
-            if value.yapko_type == "String" {
-                let value = &value.members[&String::from("value")];
-                if let Variable::Primitive(YapkoString(string)) = value {
-                    print!("{}", string);
-                }
-                return
-            } else {
-                println!("Function toString() not found in {}", value.yapko_type);
-                process::exit(1);
-            }
-        }
-
-        let to_string_object = &value.members[&String::from("toString")];
-        if let Variable::YapkoObject(yapko_function) = to_string_object {
-            // toString function will push converted string to the stack
-            execute_function(stack, yapko_function);
-            let string_object = &stack[stack.len() - 1];
-            let string_value = &string_object.members[&String::from("value")];
-            if let Variable::Primitive(YapkoString(text)) = string_value {
-                print!("{}", text);
-            } else {
-                println!("Error converting {} to String", value.name);
-            };
-            // Remove string from stack
-            stack.remove(stack.len() - 1);
-        } else {
-            println!("Error converting {} to String", value.name);
-        };
+    fn print(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        print!("{}", pop_text(stack)?);
+        Ok(())
     }
     output.insert(String::from("print"), generate_function(String::from("print"), print));
 
     // Create class for IO operations
-    fn io_read_line(stack: &mut Vec<YapkoObject>) {
+    fn io_read_line(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
         // Remove self
-        stack.remove(stack.len()-1);
+        pop(stack)?;
 
         // Read line
         let mut line = String::new();
-        std::io::stdin().read_line(&mut line).expect("TODO: panic message");
+        std::io::stdin().read_line(&mut line).map_err(
+            |error| YapkoError::new(ErrorKind::IOError, error.to_string())
+        )?;
 
         // Push line to stack
-        let string = generate_string("$String".to_string(), line);
-        stack.push(string);
-    }
-    let mut io_class = YapkoObject {
-        name: "IO".to_string(),
-        yapko_type: "class".to_string(),
-        parent: "".to_string(),
-        members: hashmap![
-            String::from("readLine") => Variable::YapkoObject(
-                generate_function(
-                    "readLine".to_string(),
-                    io_read_line
-                )
-            )
-        ]
-    };
-    output.insert(String::from("IO"), io_class);
+        stack.push(generate_string(line));
+        Ok(())
+    }
+    let io_class = generate_class(String::from("IO"), hashmap![
+        String::from("readLine") => generate_function(String::from("readLine"), io_read_line)
+    ]);
+    output.insert(String::from("IO"), Value::new_object(io_class));
 
     // Create class for random number operations
-    fn random_generate(stack: &mut Vec<YapkoObject>) {
+    fn random_generate(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
         // Remove self
-        stack.remove(stack.len()-1);
+        pop(stack)?;
 
         // Create rng thread
         let mut rng = rand::thread_rng();
         let number = rng.gen();
 
         // Send number to stack
-        stack.push(generate_int("$int".to_string(), number))
-    }
-    let mut random_class = YapkoObject {
-        name: "Random".to_string(),
-        yapko_type: "class".to_string(),
-        parent: "".to_string(),
-        members: hashmap![
-            String::from("generate") => Variable::YapkoObject(
-                generate_function(
-                    "generate".to_string(),
-                    random_generate
-                )
-            )
-        ]
-    };
-    output.insert(String::from("Random"), random_class);
-
-    // Create class for integers
-    let mut int_class = generate_int(String::from("Int"), 0);
-    int_class.yapko_type = String::from("class");
-    output.insert(String::from("Float"), int_class);
-
-    // Create class for floats
-    let mut float_class = generate_float(String::from("Float"), 0.0);
-    float_class.yapko_type = String::from("class");
-    output.insert(String::from("Float"), float_class);
-
-    // Create class for strings
-    let mut string_class = generate_string(String::from("String"), String::new());
-    string_class.yapko_type = String::from("class");
-    output.insert(String::from("String"), string_class);
-
-    // Create class for booleans
-    let mut boolean_class = generate_boolean(String::from("Boolean"), false);
-    boolean_class.yapko_type = String::from("class");
-    output.insert(String::from("Boolean"), boolean_class);
+        stack.push(Value::Int(number));
+        Ok(())
+    }
+    let random_class = generate_class(String::from("Random"), hashmap![
+        String::from("generate") => generate_function(String::from("generate"), random_generate)
+    ]);
+    output.insert(String::from("Random"), Value::new_object(random_class));
+
+    // Classes of the built-in types, so they can be used as argument types
+    for (name, class) in generate_types() {
+        output.insert(name, Value::Object(class));
+    }
 
     return output;
 }
 
-#[derive(Clone)]
-pub enum Variable {
-    Primitive(Primitive),
-    YapkoObject(YapkoObject),
+// Classes holding the methods of the values of built-in types, by the name of the type
+pub fn generate_types() -> HashMap<String, ObjectRef> {
+    let classes = [
+        generate_int_class(),
+        generate_float_class(),
+        generate_string_class(),
+        generate_boolean_class(),
+        generate_range_class(),
+        generate_iterator_class(),
+    ];
+    classes.into_iter().map(|class| (class.name.clone(), Rc::new(RefCell::new(class)))).collect()
 }
 
-#[derive(Clone)]
-pub enum Primitive {
-    Int(i32),
-    Float(f64),
-    YapkoString(String),
-    YapkoFunction(Vec<u8>, Vec<(usize, String)>),
-    Function(fn (stack: &mut Vec<YapkoObject>)),
-    Boolean(bool),
-    Null
-}
+fn generate_int_class() -> YapkoObject {
+    // Take both operands of a binary operator, right one has to be an Int as well
+    fn pop_operands(stack: &mut Vec<Value>, function: &str) -> Result<(i32, i32), YapkoError> {
+        let right = pop(stack)?;
+        let left = pop(stack)?;
+
+        match (left, right) {
+            (Value::Int(left_value), Value::Int(right_value)) => Ok((left_value, right_value)),
+            (_, right) => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Int does not implement {}({})", function, right.yapko_type())
+            ))
+        }
+    }
 
-#[derive(Clone)]
-pub struct YapkoObject {
-    pub name: String,
-    pub yapko_type: String,
-    pub parent: String,
-    pub members: HashMap<String, Variable>,
-}
+    // Result of an operation which is None when it does not fit into Int
+    fn checked(result: Option<i32>, left: i32, operator: &str, right: i32) -> Result<Value, YapkoError> {
+        result.map(Value::Int).ok_or_else(|| YapkoError::new(
+            ErrorKind::ValueError,
+            format!("Cannot compute {} {} {}, result does not fit into Int", left, operator, right)
+        ))
+    }
 
-pub fn generate_int(name: String, value: i32) -> YapkoObject {
-    fn to_string(stack: &mut Vec<YapkoObject>) {
-        let int = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        if let Variable::Primitive(Primitive::Int(value)) = int.members["value"] {
-            stack.push(generate_string(int.name, value.to_string()));
-        } else {
-            println!("Error converting {} to String", int.name);
-            return;
-        };
-    }
-
-    fn add(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
-
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if right.yapko_type != "Int" {
-                println!("Int does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_int(left.name, left_value + right_value));
+    fn to_string(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        match pop(stack)? {
+            Value::Int(value) => {
+                stack.push(generate_string(value.to_string()));
+                Ok(())
             }
+            value => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Error converting {} to String", value.yapko_type())
+            ))
         }
     }
-    fn sub(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
 
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if right.yapko_type != "Int" {
-                println!("Int does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_int(left.name, left_value - right_value));
-            }
+    fn add(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "add")?;
+        stack.push(checked(left_value.checked_add(right_value), left_value, "+", right_value)?);
+        Ok(())
+    }
+    fn sub(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "sub")?;
+        stack.push(checked(left_value.checked_sub(right_value), left_value, "-", right_value)?);
+        Ok(())
+    }
+    fn div(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "div")?;
+        if right_value == 0 {
+            return Err(YapkoError::new(ErrorKind::DivisionByZero, String::from("Division by zero")));
         }
+        stack.push(checked(left_value.checked_div(right_value), left_value, "/", right_value)?);
+        Ok(())
     }
-    fn div(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
-
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if right.yapko_type != "Int" {
-                println!("Int does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_int(left.name, left_value / right_value));
-            }
+    fn mul(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "mul")?;
+        stack.push(checked(left_value.checked_mul(right_value), left_value, "*", right_value)?);
+        Ok(())
+    }
+    fn mod_n(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "mod")?;
+        if right_value == 0 {
+            return Err(YapkoError::new(ErrorKind::DivisionByZero, String::from("Modulo by zero")));
         }
+        stack.push(checked(left_value.checked_rem(right_value), left_value, "%", right_value)?);
+        Ok(())
     }
-    fn mul(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
 
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if right.yapko_type != "Int" {
-                println!("Int does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_int(left.name, left_value * right_value));
-            }
-        }
+    fn smaller_than(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "smallerThan")?;
+        stack.push(Value::Boolean(left_value < right_value));
+        Ok(())
     }
-    fn smaller_than(stack: &mut Vec<YapkoObject>) {
-        let left = stack[stack.len()-2].clone();
-        let right= stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
-
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_boolean("$bool".parse().unwrap(), left_value < right_value))
-            } else {
-                println!("Cannot compare Int to not-Int")
+    fn greater_than(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "greaterThan")?;
+        stack.push(Value::Boolean(left_value > right_value));
+        Ok(())
+    }
+    fn negate(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        match pop(stack)? {
+            Value::Int(value) => {
+                let negated = value.checked_neg().ok_or_else(|| YapkoError::new(
+                    ErrorKind::ValueError,
+                    format!("Cannot negate {}, result does not fit into Int", value)
+                ))?;
+                stack.push(Value::Int(negated));
+                Ok(())
             }
+            value => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Error negating {}", value.yapko_type())
+            ))
         }
     }
 
-    fn greater_than(stack: &mut Vec<YapkoObject>) {
-        let left = stack[stack.len()-2].clone();
-        let right= stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
+    fn smaller_or_equal(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "smallerOrEqual")?;
+        stack.push(Value::Boolean(left_value <= right_value));
+        Ok(())
+    }
+    fn greater_or_equal(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "greaterOrEqual")?;
+        stack.push(Value::Boolean(left_value >= right_value));
+        Ok(())
+    }
+    fn equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "equalTo")?;
+        stack.push(Value::Boolean(left_value == right_value));
+        Ok(())
+    }
+    fn not_equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "notEqualTo")?;
+        stack.push(Value::Boolean(left_value != right_value));
+        Ok(())
+    }
 
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_boolean("$bool".parse().unwrap(), left_value > right_value))
-            } else {
-                println!("Cannot compare Int to not-Int")
-            }
+    generate_class(String::from("Int"), hashmap![
+        String::from("toString") => generate_function(String::from("toString"), to_string),
+        String::from("add") => generate_function(String::from("add"), add),
+        String::from("sub") => generate_function(String::from("sub"), sub),
+        String::from("mul") => generate_function(String::from("mul"), mul),
+        String::from("negate") => generate_function(String::from("negate"), negate),
+        String::from("div") => generate_function(String::from("div"), div),
+        String::from("mod") => generate_function(String::from("mod"), mod_n),
+        String::from("smallerThan") => generate_function(String::from("smallerThan"), smaller_than),
+        String::from("greaterThan") => generate_function(String::from("greaterThan"), greater_than),
+        String::from("smallerOrEqual") => generate_function(String::from("smallerOrEqual"), smaller_or_equal),
+        String::from("greaterOrEqual") => generate_function(String::from("greaterOrEqual"), greater_or_equal),
+        String::from("equalTo") => generate_function(String::from("equalTo"), equal_to),
+        String::from("notEqualTo") => generate_function(String::from("notEqualTo"), not_equal_to)
+    ])
+}
+
+fn generate_float_class() -> YapkoObject {
+    // Take both operands of a binary operator, right one has to be a Float as well
+    fn pop_operands(stack: &mut Vec<Value>, function: &str) -> Result<(f64, f64), YapkoError> {
+        let right = pop(stack)?;
+        let left = pop(stack)?;
+
+        match (left, right) {
+            (Value::Float(left_value), Value::Float(right_value)) => Ok((left_value, right_value)),
+            (_, right) => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Float does not implement {}({})", function, right.yapko_type())
+            ))
         }
     }
 
-    fn equal_to(stack: &mut Vec<YapkoObject>) {
-        let left = stack[stack.len()-2].clone();
-        let right= stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
-
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(
-                    generate_boolean(
-                        "$bool".parse().unwrap(),
-                        left_value == right_value
-                    )
-                )
-            } else {
-                println!("Cannot compare Int to not-Int")
+    fn to_string(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        match pop(stack)? {
+            Value::Float(value) => {
+                stack.push(generate_string(value.to_string()));
+                Ok(())
             }
+            value => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Error converting {} to String", value.yapko_type())
+            ))
         }
     }
 
-    fn mod_n(stack: &mut Vec<YapkoObject>) {
-        let left = stack[stack.len()-2].clone();
-        let right= stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
+    fn add(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "add")?;
+        stack.push(Value::Float(left_value + right_value));
+        Ok(())
+    }
+    fn sub(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "sub")?;
+        stack.push(Value::Float(left_value - right_value));
+        Ok(())
+    }
+    fn div(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "div")?;
+        if right_value == 0.0 {
+            return Err(YapkoError::new(ErrorKind::DivisionByZero, String::from("Division by zero")));
+        }
+        stack.push(Value::Float(left_value / right_value));
+        Ok(())
+    }
+    fn mul(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "mul")?;
+        stack.push(Value::Float(left_value * right_value));
+        Ok(())
+    }
 
-        if let Variable::Primitive(Primitive::Int(left_value)) = left.members["value"] {
-            if let Variable::Primitive(Primitive::Int(right_value)) = right.members["value"] {
-                stack.push(generate_int("$int".to_string(), left_value % right_value))
-            } else {
-                println!("lol")
+    fn smaller_than(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "smallerThan")?;
+        stack.push(Value::Boolean(left_value < right_value));
+        Ok(())
+    }
+    fn greater_than(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "greaterThan")?;
+        stack.push(Value::Boolean(left_value > right_value));
+        Ok(())
+    }
+    fn negate(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        match pop(stack)? {
+            Value::Float(value) => {
+                stack.push(Value::Float(-value));
+                Ok(())
             }
+            value => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Error negating {}", value.yapko_type())
+            ))
         }
     }
 
-    YapkoObject {
-        name,
-        parent: "".to_string(),
-        yapko_type: "Int".parse().unwrap(),
-        members: hashmap![String::from("value") => Variable::Primitive(Primitive::Int(value)),
-            String::from("toString") => Variable::YapkoObject(generate_function(String::from("toString"), to_string)),
-            String::from("add") => Variable::YapkoObject(generate_function(String::from("add"), add)),
-            String::from("sub") => Variable::YapkoObject(generate_function(String::from("sub"), sub)),
-            String::from("mul") => Variable::YapkoObject(generate_function(String::from("mul"), mul)),
-            String::from("div") => Variable::YapkoObject(generate_function(String::from("div"), div)),
-            String::from("mod") => Variable::YapkoObject(generate_function(String::from("mod"), mod_n)),
-            String::from("smallerThan") => Variable::YapkoObject(generate_function(String::from("smallerThan"), smaller_than)),
-            String::from("equalTo") => Variable::YapkoObject(generate_function(String::from("smallerThan"), equal_to)),
-            String::from("greaterThan") => Variable::YapkoObject(generate_function(String::from("greaterThan"), greater_than))
-        ]
+    fn smaller_or_equal(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "smallerOrEqual")?;
+        stack.push(Value::Boolean(left_value <= right_value));
+        Ok(())
+    }
+    fn greater_or_equal(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "greaterOrEqual")?;
+        stack.push(Value::Boolean(left_value >= right_value));
+        Ok(())
     }
+    fn equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "equalTo")?;
+        stack.push(Value::Boolean(left_value == right_value));
+        Ok(())
+    }
+    fn not_equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "notEqualTo")?;
+        stack.push(Value::Boolean(left_value != right_value));
+        Ok(())
+    }
+
+    generate_class(String::from("Float"), hashmap![
+        String::from("toString") => generate_function(String::from("toString"), to_string),
+        String::from("add") => generate_function(String::from("add"), add),
+        String::from("sub") => generate_function(String::from("sub"), sub),
+        String::from("mul") => generate_function(String::from("mul"), mul),
+        String::from("negate") => generate_function(String::from("negate"), negate),
+        String::from("div") => generate_function(String::from("div"), div),
+        String::from("smallerThan") => generate_function(String::from("smallerThan"), smaller_than),
+        String::from("greaterThan") => generate_function(String::from("greaterThan"), greater_than),
+        String::from("smallerOrEqual") => generate_function(String::from("smallerOrEqual"), smaller_or_equal),
+        String::from("greaterOrEqual") => generate_function(String::from("greaterOrEqual"), greater_or_equal),
+        String::from("equalTo") => generate_function(String::from("equalTo"), equal_to),
+        String::from("notEqualTo") => generate_function(String::from("notEqualTo"), not_equal_to)
+    ])
 }
 
-pub fn generate_float(name: String, value: f64) -> YapkoObject {
-    fn to_string(stack: &mut Vec<YapkoObject>) {
-        let float = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        if let Variable::Primitive(Primitive::Float(value)) = float.members["value"] {
-            stack.push(generate_string(float.name, value.to_string()));
-        } else {
-            println!("Error converting {} to String", float.name);
-            return;
-        };
-    }
-
-    fn add(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
-
-        if let Variable::Primitive(Primitive::Float(left_value)) = left.members["value"] {
-            if right.yapko_type != "Float" {
-                println!("Float does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Float(right_value)) = right.members["value"] {
-                stack.push(generate_float(left.name, left_value + right_value));
-            }
+// Text of a String value, None for any other value
+fn string_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Object(object) => match &object.borrow().value {
+            Primitive::YapkoString(string) => Some(string.clone()),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn generate_string_class() -> YapkoObject {
+    // Take both operands of a binary operator, right one has to be a String as well
+    fn pop_operands(stack: &mut Vec<Value>, function: &str) -> Result<(String, String), YapkoError> {
+        let right = pop(stack)?;
+        let left = pop(stack)?;
+
+        match (string_value(&left), string_value(&right)) {
+            (Some(left_value), Some(right_value)) => Ok((left_value, right_value)),
+            _ => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("String does not implement {}({})", function, right.yapko_type())
+            ))
         }
     }
-    fn sub(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
 
-        if let Variable::Primitive(Primitive::Float(left_value)) = left.members["value"] {
-            if right.yapko_type != "Float" {
-                println!("Float does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Float(right_value)) = right.members["value"] {
-                stack.push(generate_float(left.name, left_value - right_value));
-            }
+    fn to_int(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        if let Some(string) = string_value(&pop(stack)?) {
+            let int = string.trim().parse::<i32>().map_err(|_| YapkoError::new(
+                ErrorKind::ValueError,
+                format!("Cannot convert \"{}\" to Int", string.trim())
+            ))?;
+            stack.push(Value::Int(int))
         }
+        Ok(())
     }
-    fn div(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
 
-        if let Variable::Primitive(Primitive::Float(left_value)) = left.members["value"] {
-            if right.yapko_type != "Float" {
-                println!("Float does not implement add({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Float(right_value)) = right.members["value"] {
-                stack.push(generate_float(left.name, left_value / right_value));
-            }
+    // Iterator over the characters, each as a String
+    fn iter(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        if let Some(string) = string_value(&pop(stack)?) {
+            let characters: Vec<Value> = string.chars()
+                .map(|character| generate_string(character.to_string()))
+                .collect();
+            stack.push(generate_iterator(characters.into_iter()));
         }
+        Ok(())
     }
-    fn mul(stack: &mut Vec<YapkoObject>) {
-        let left  = stack[stack.len()-2].clone();
-        let right = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
 
-        if let Variable::Primitive(Primitive::Float(left_value)) = left.members["value"] {
-            if right.yapko_type != "Float" {
-                println!("Float does not implement mul({})", right.yapko_type);
-            }
-            if let Variable::Primitive(Primitive::Float(right_value)) = right.members["value"] {
-                stack.push(generate_float(left.name, left_value * right_value));
-            }
+    // Strings are compared by their characters, e.g. "apple" < "banana"
+    fn smaller_than(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "smallerThan")?;
+        stack.push(Value::Boolean(left_value < right_value));
+        Ok(())
+    }
+    fn greater_than(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "greaterThan")?;
+        stack.push(Value::Boolean(left_value > right_value));
+        Ok(())
+    }
+    fn smaller_or_equal(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "smallerOrEqual")?;
+        stack.push(Value::Boolean(left_value <= right_value));
+        Ok(())
+    }
+    fn greater_or_equal(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "greaterOrEqual")?;
+        stack.push(Value::Boolean(left_value >= right_value));
+        Ok(())
+    }
+    fn equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "equalTo")?;
+        stack.push(Value::Boolean(left_value == right_value));
+        Ok(())
+    }
+    fn not_equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "notEqualTo")?;
+        stack.push(Value::Boolean(left_value != right_value));
+        Ok(())
+    }
+
+    generate_class(String::from("String"), hashmap![
+        String::from("toInt") => generate_function(String::from("toInt"), to_int),
+        String::from("iter") => generate_function(String::from("iter"), iter),
+        String::from("smallerThan") => generate_function(String::from("smallerThan"), smaller_than),
+        String::from("greaterThan") => generate_function(String::from("greaterThan"), greater_than),
+        String::from("smallerOrEqual") => generate_function(String::from("smallerOrEqual"), smaller_or_equal),
+        String::from("greaterOrEqual") => generate_function(String::from("greaterOrEqual"), greater_or_equal),
+        String::from("equalTo") => generate_function(String::from("equalTo"), equal_to),
+        String::from("notEqualTo") => generate_function(String::from("notEqualTo"), not_equal_to)
+    ])
+}
+
+fn generate_boolean_class() -> YapkoObject {
+    // Take both operands of a binary operator, right one has to be a Boolean as well
+    fn pop_operands(stack: &mut Vec<Value>, function: &str) -> Result<(bool, bool), YapkoError> {
+        let right = pop(stack)?;
+        let left = pop(stack)?;
+
+        match (left, right) {
+            (Value::Boolean(left_value), Value::Boolean(right_value)) => Ok((left_value, right_value)),
+            (_, right) => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Boolean does not implement {}({})", function, right.yapko_type())
+            ))
         }
     }
-    fn smaller_than(stack: &mut Vec<YapkoObject>) {
-        let left = stack[stack.len()-2].clone();
-        let right= stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
-
-        if let Variable::Primitive(Primitive::Float(left_value)) = left.members["value"] {
-            if let Variable::Primitive(Primitive::Float(right_value)) = right.members["value"] {
-                stack.push(generate_boolean("$bool".parse().unwrap(), left_value < right_value))
-            } else {
-                println!("Cannot compare Float to not-Float")
+
+    fn to_string(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        match pop(stack)? {
+            Value::Boolean(value) => {
+                stack.push(generate_string(value.to_string()));
+                Ok(())
             }
+            value => Err(YapkoError::new(
+                ErrorKind::TypeError,
+                format!("Error converting {} to String", value.yapko_type())
+            ))
         }
     }
 
-    fn greater_than(stack: &mut Vec<YapkoObject>) {
-        let left = stack[stack.len()-2].clone();
-        let right= stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        stack.remove(stack.len()-1);
+    // Booleans have no order, only equality
+    fn equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "equalTo")?;
+        stack.push(Value::Boolean(left_value == right_value));
+        Ok(())
+    }
+    fn not_equal_to(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (left_value, right_value) = pop_operands(stack, "notEqualTo")?;
+        stack.push(Value::Boolean(left_value != right_value));
+        Ok(())
+    }
+
+    generate_class(String::from("Boolean"), hashmap![
+        String::from("toString") => generate_function(String::from("toString"), to_string),
+        String::from("equalTo") => generate_function(String::from("equalTo"), equal_to),
+        String::from("notEqualTo") => generate_function(String::from("notEqualTo"), not_equal_to)
+    ])
+}
 
-        if let Variable::Primitive(Primitive::Float(left_value)) = left.members["value"] {
-            if let Variable::Primitive(Primitive::Float(right_value)) = right.members["value"] {
-                stack.push(generate_boolean("$bool".parse().unwrap(), left_value > right_value))
-            } else {
-                println!("Cannot compare Float to not-Float")
+// Ints from start up to (but excluding) end, created by `start..end`
+fn generate_range_class() -> YapkoObject {
+    fn bounds(range: &Value) -> Result<(i32, i32), YapkoError> {
+        if let Value::Object(object) = range {
+            if let Primitive::Range(start, end) = object.borrow().value {
+                return Ok((start, end));
             }
         }
+        Err(YapkoError::new(ErrorKind::TypeError, format!("Expected Range, but got {}", range.yapko_type())))
     }
 
-    YapkoObject {
-        name,
-        parent: "".to_string(),
-        yapko_type: "Float".parse().unwrap(),
-        members: hashmap![String::from("value") => Variable::Primitive(Primitive::Float(value)),
-            String::from("toString") => Variable::YapkoObject(generate_function(String::from("toString"), to_string)),
-            String::from("add") => Variable::YapkoObject(generate_function(String::from("add"), add)),
-            String::from("sub") => Variable::YapkoObject(generate_function(String::from("sub"), sub)),
-            String::from("mul") => Variable::YapkoObject(generate_function(String::from("mul"), mul)),
-            String::from("div") => Variable::YapkoObject(generate_function(String::from("div"), div)),
-            String::from("smallerThan") => Variable::YapkoObject(generate_function(String::from("smallerThan"), smaller_than)),
-            String::from("greaterThan") => Variable::YapkoObject(generate_function(String::from("greaterThan"), greater_than))
-        ]
+    fn iter(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (start, end) = bounds(&pop(stack)?)?;
+        stack.push(generate_iterator((start..end).map(Value::Int)));
+        Ok(())
     }
-}
 
-pub fn generate_string(name: String, value: String) -> YapkoObject {
-    fn to_int(stack: &mut Vec<YapkoObject>) {
-        let var = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
+    fn to_string(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let (start, end) = bounds(&pop(stack)?)?;
+        stack.push(generate_string(format!("{}..{}", start, end)));
+        Ok(())
+    }
 
-        if let Variable::Primitive(YapkoString(string)) = var.members["value"].clone() {
-            let int = string.trim().parse::<i32>().unwrap();
-            stack.push(generate_int("$int".to_string(), int))
+    generate_class(String::from("Range"), hashmap![
+        String::from("iter") => generate_function(String::from("iter"), iter),
+        String::from("toString") => generate_function(String::from("toString"), to_string)
+    ])
+}
+
+// Built-in implementation of the iteration protocol used by `for`: iter() returns an object whose next() returns
+// the following value, or null when there are no more values. User classes implement both as methods, ending
+// with `return` without a value.
+fn generate_iterator_class() -> YapkoObject {
+    fn next(stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        let mut value = None;
+        if let Value::Object(iterator) = pop(stack)? {
+            if let Primitive::Iterator(values) = &mut iterator.borrow_mut().value {
+                value = values.next();
+            }
         }
+        stack.push(value.unwrap_or(Value::Null));
+        Ok(())
     }
 
-    YapkoObject {
-        name,
-        parent: "".to_string(),
-        yapko_type: "String".parse().unwrap(),
-        members: hashmap![
-            String::from("value") => Variable::Primitive(Primitive::YapkoString(value)),
-            String::from("toInt") => Variable::YapkoObject(generate_function("toInt".to_string(), to_int))
-        ]
+    // Iterators are iterable themselves, the iterator pushed as self is the result
+    fn iter(_stack: &mut Vec<Value>) -> Result<(), YapkoError> {
+        Ok(())
     }
+
+    generate_class(String::from("Iterator"), hashmap![
+        String::from("next") => generate_function(String::from("next"), next),
+        String::from("iter") => generate_function(String::from("iter"), iter)
+    ])
 }
 
-pub fn generate_boolean(name: String, value: bool) -> YapkoObject {
-    fn to_string(stack: &mut Vec<YapkoObject>) {
-        let boolean = stack[stack.len()-1].clone();
-        stack.remove(stack.len()-1);
-        if let Variable::Primitive(Primitive::Boolean(value)) = boolean.members["value"] {
-            stack.push(generate_string(boolean.name, value.to_string()));
-        } else {
-            println!("Error converting {} to String", boolean.name);
-            return;
-        };
-    }
-    YapkoObject {
-        name,
-        parent: "".to_string(),
-        yapko_type: "Boolean".parse().unwrap(),
-        members: hashmap![
-            String::from("value") => Variable::Primitive(Primitive::Boolean(value)),
-            String::from("toString") => Variable::YapkoObject(generate_function(String::from("toString"), to_string))
-        ]
-    }
+pub fn generate_string(value: String) -> Value {
+    Value::new_object(YapkoObject {
+        name: String::new(),
+        yapko_type: String::from("String"),
+        value: Primitive::YapkoString(value),
+        members: HashMap::new()
+    })
+}
+
+pub fn generate_range(start: i32, end: i32) -> Value {
+    Value::new_object(YapkoObject {
+        name: String::new(),
+        yapko_type: String::from("Range"),
+        value: Primitive::Range(start, end),
+        members: HashMap::new()
+    })
+}
+
+// Shared by every reference to it, so next() advances all of them
+pub fn generate_iterator(values: impl Iterator<Item = Value> + 'static) -> Value {
+    Value::new_object(YapkoObject {
+        name: String::new(),
+        yapko_type: String::from("Iterator"),
+        value: Primitive::Iterator(Box::new(values)),
+        members: HashMap::new()
+    })
 }
 
-pub fn generate_yapko_function(name: String, bytecode: Vec<u8>, mut used_variables: Vec<(usize, String)>, scope: usize) -> YapkoObject {
-    used_variables.push((scope, name.clone()));
+pub fn generate_class(name: String, members: HashMap<String, Value>) -> YapkoObject {
     YapkoObject {
         name,
-        parent: "".to_string(),
-        yapko_type: String::from("YapkoFunction"),
-        members: hashmap!(
-            String::from("value") => Variable::Primitive(Primitive::YapkoFunction(bytecode, used_variables))
-        )
+        yapko_type: String::from("class"),
+        value: Primitive::NativeClass,
+        members
     }
 }
 
-pub fn generate_function(name: String, function: fn(stack: &mut Vec<YapkoObject>)) -> YapkoObject {
-    YapkoObject {
+pub fn generate_yapko_function(
+    name: String,
+    bytecode: Rc<Chunk>,
+    start: usize,
+    parameter_count: usize,
+    environment: Rc<[Scope]>
+) -> Value {
+    Value::new_object(YapkoObject {
         name,
-        parent: "".to_string(),
-        yapko_type: String::from("Function"),
-        members: hashmap![String::from("value") => Variable::Primitive(Primitive::Function(function))]
-    }
+        yapko_type: String::from("YapkoFunction"),
+        value: Primitive::YapkoFunction(bytecode, start, parameter_count, environment),
+        members: HashMap::new()
+    })
 }
 
-pub fn generate_null(name: String) -> YapkoObject {
-    YapkoObject {
+pub fn generate_function(name: String, function: NativeFunction) -> Value {
+    Value::new_object(YapkoObject {
         name,
-        parent: "".to_string(),
-        yapko_type: "Null".parse().unwrap(),
-        members: hashmap!(String::from("value") => Variable::Primitive(Primitive::Null))
-    }
+        yapko_type: String::from("Function"),
+        value: Primitive::Function(function),
+        members: HashMap::new()
+    })
 }
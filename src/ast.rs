@@ -0,0 +1,189 @@
+use strum_macros::Display;
+use crate::diagnostic::Position;
+
+// Displayed as written in the source
+#[derive(Clone, Copy, Debug, Display, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum BinaryOperator {
+    #[strum(serialize = "+")]
+    Add,
+    #[strum(serialize = "-")]
+    Sub,
+    #[strum(serialize = "*")]
+    Mul,
+    #[strum(serialize = "/")]
+    Div,
+    #[strum(serialize = "%")]
+    Mod,
+    #[strum(serialize = "<")]
+    Less,
+    #[strum(serialize = "<=")]
+    LessEqual,
+    #[strum(serialize = ">")]
+    Greater,
+    #[strum(serialize = ">=")]
+    GreaterEqual,
+    #[strum(serialize = "==")]
+    Equal,
+    #[strum(serialize = "!=")]
+    NotEqual,
+    And,
+    Or,
+    Xor,
+    #[strum(serialize = "..")]
+    Range,
+}
+
+#[derive(Clone, Copy, Debug, Display, PartialEq)]
+pub enum UnaryOperator {
+    #[strum(serialize = "-")]
+    Neg,
+    #[strum(serialize = "+")]
+    Plus,
+    #[strum(serialize = "!")]
+    Not,
+}
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Int(i32, Position),
+    Float(f64, Position),
+    String(String, Position),
+    Boolean(bool, Position),
+    Variable(String, Position),
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        position: Position,
+    },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+        position: Position,
+    },
+    // Target is a variable or a member
+    Assign {
+        target: Box<Expr>,
+        value: Box<Expr>,
+        position: Position,
+    },
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        position: Position,
+    },
+    Member {
+        object: Box<Expr>,
+        name: String,
+        position: Position,
+    },
+    // `super.name`, member of the superclass of the class being defined, bound to `self`
+    Super {
+        name: String,
+        position: Position,
+    },
+}
+
+impl Expr {
+    // Span of the whole expression
+    pub fn position(&self) -> &Position {
+        match self {
+            Expr::Int(_, position) | Expr::Float(_, position) | Expr::String(_, position) |
+            Expr::Boolean(_, position) | Expr::Variable(_, position) => position,
+            Expr::Binary { position, .. } | Expr::Unary { position, .. } | Expr::Assign { position, .. } |
+            Expr::Call { position, .. } | Expr::Member { position, .. } | Expr::Super { position, .. } => position,
+        }
+    }
+}
+
+// Argument of a function declaration, e.g. `n: Int`
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: String,
+    pub type_name: String,
+}
+
+// Statements between '{' and '}'
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub body: Vec<Stmt>,
+    // Position of the closing '}'
+    pub end: Position,
+}
+
+// Branch run when the condition of an `if` is false
+#[derive(Clone, Debug)]
+pub enum Else {
+    Block(Block),
+    // `else if`, the statement is a Stmt::If
+    If(Box<Stmt>),
+}
+
+// Every statement keeps the position of its first line (e.g. `while i < 3`), not of its body
+#[derive(Clone, Debug)]
+pub enum Stmt {
+    Expression(Expr),
+    Let {
+        name: String,
+        value: Option<Expr>,
+        position: Position,
+    },
+    Function {
+        name: String,
+        params: Vec<Param>,
+        body: Block,
+        position: Position,
+    },
+    Return {
+        value: Option<Expr>,
+        position: Position,
+    },
+    If {
+        condition: Expr,
+        body: Block,
+        otherwise: Option<Else>,
+        position: Position,
+    },
+    While {
+        condition: Expr,
+        body: Block,
+        position: Position,
+    },
+    // `for variable in iterable`, runs the body for every value returned by `iterable.iter().next()`
+    For {
+        variable: String,
+        iterable: Expr,
+        body: Block,
+        position: Position,
+    },
+    Break(Position),
+    Continue(Position),
+    // `class name : superclass`
+    Class {
+        name: String,
+        superclass: Option<String>,
+        body: Block,
+        position: Position,
+    },
+    Execute {
+        file: String,
+        position: Position,
+    },
+    // Bare '{' ... '}' with its own scope
+    Block(Block, Position),
+}
+
+impl Stmt {
+    pub fn position(&self) -> &Position {
+        match self {
+            Stmt::Expression(expression) => expression.position(),
+            Stmt::Let { position, .. } | Stmt::Function { position, .. } | Stmt::Return { position, .. } |
+            Stmt::If { position, .. } | Stmt::While { position, .. } | Stmt::For { position, .. } |
+            Stmt::Class { position, .. } | Stmt::Execute { position, .. } | Stmt::Block(_, position) |
+            Stmt::Break(position) | Stmt::Continue(position) => position,
+        }
+    }
+}
+
+pub type Program = Vec<Stmt>;
@@ -1,304 +1,711 @@
 use std::collections::HashMap;
-use std::process;
-use crate::lexer::{Keywords, TokenType};
-use crate::parser::Node;
+use strum_macros::{Display, FromRepr};
+use crate::ast::{BinaryOperator, Block, Else, Expr, Stmt, UnaryOperator};
+use crate::diagnostic::Diagnostic;
+use crate::diagnostic::Position;
+
+// Jump offsets are stored as little-endian u32 relative to the end of the operand
+pub const OFFSET_SIZE: usize = 4;
+// Strings and identifiers are stored as little-endian u32 index into the constant pool
+pub const CONSTANT_SIZE: usize = 4;
+
+// Bump whenever numbers in OpCode or their operands change, so old compiled files are rejected
+pub const OPCODE_SET_VERSION: u16 = 6;
+
+// Instruction of the bytecode, displayed as its mnemonic
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Display, FromRepr, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum OpCode {
+    // Separator between instructions, does nothing
+    #[strum(serialize = ";")]
+    Nop = 0,
+    Get = 1,
+    // Create variable in the current scope holding the value on the stack
+    Define = 2,
+    PushNull = 3,
+    PushFloat = 4,
+    PushInt = 5,
+    PushStr = 6,
+    #[strum(serialize = "+")]
+    Add = 7,
+    #[strum(serialize = "-")]
+    Sub = 8,
+    #[strum(serialize = "*")]
+    Mul = 9,
+    #[strum(serialize = "/")]
+    Div = 10,
+    // Store the value on the stack into an existing variable, the value stays on the stack
+    Set = 11,
+    Call = 21,
+    FunStart = 22,
+    FunEnd = 23,
+    ScopeNew = 24,
+    ScopeEnd = 25,
+    Arg = 26,
+    ArgType = 27,
+    PushBool = 28,
+    Or = 29,
+    Xor = 30,
+    And = 31,
+    #[strum(serialize = "!")]
+    Not = 32,
+    Close = 36,
+    #[strum(serialize = "<")]
+    Less = 37,
+    #[strum(serialize = ">")]
+    Greater = 38,
+    #[strum(serialize = "<=")]
+    LessEqual = 39,
+    #[strum(serialize = ">=")]
+    GreaterEqual = 40,
+    #[strum(serialize = "==")]
+    Equal = 41,
+    #[strum(serialize = "!=")]
+    NotEqual = 42,
+    #[strum(serialize = "%")]
+    Mod = 43,
+    #[strum(serialize = ".")]
+    Member = 44,
+    Class = 45,
+    Return = 46,
+    Execute = 47,
+    Jump = 48,
+    JumpIfFalse = 49,
+    Loop = 50,
+    Neg = 51,
+    #[strum(serialize = "..")]
+    Range = 52,
+    // Bind the value returned by next() to the loop variable, or leave the loop if it is null
+    ForNext = 53,
+    // Make the class being defined a subclass of the class on the stack
+    Inherit = 54,
+    // Member of the superclass on the stack, bound to the object below it
+    Super = 55,
+    // Store the value on the stack into a member of the object below it, the value replaces both on the stack
+    SetMember = 56,
+    // Discard the value on the stack, e.g. the result of a call used as a statement
+    Pop = 57,
+}
+
+impl TryFrom<u8> for OpCode {
+    // Byte which is not an opcode
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<OpCode, u8> {
+        OpCode::from_repr(byte).ok_or(byte)
+    }
+}
+
+impl OpCode {
+    // Number of operand bytes following the instruction
+    pub fn operand_size(self) -> usize {
+        match self {
+            OpCode::Get | OpCode::Define | OpCode::Set | OpCode::SetMember | OpCode::PushStr | OpCode::FunEnd |
+            OpCode::Arg | OpCode::ArgType | OpCode::Member | OpCode::Class | OpCode::Execute | OpCode::Super => {
+                CONSTANT_SIZE
+            }
+            // Name, number of parameters and length of the body
+            OpCode::FunStart => CONSTANT_SIZE + 1 + OFFSET_SIZE,
+            OpCode::ForNext => CONSTANT_SIZE + OFFSET_SIZE,
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => OFFSET_SIZE,
+            OpCode::PushInt => 4,
+            OpCode::PushFloat => 8,
+            // Boolean value or number of arguments / returned values
+            OpCode::PushBool | OpCode::Call | OpCode::Return => 1,
+            _ => 0
+        }
+    }
+
+    // Instruction refers to the constant pool with its (first) operand
+    pub fn has_constant(self) -> bool {
+        matches!(
+            self,
+            OpCode::Get | OpCode::Define | OpCode::Set | OpCode::SetMember | OpCode::PushStr | OpCode::FunStart |
+            OpCode::FunEnd | OpCode::Arg | OpCode::ArgType | OpCode::Member | OpCode::Class | OpCode::Execute |
+            OpCode::ForNext | OpCode::Super
+        )
+    }
+}
+
+// Compiled code together with its debug line table and constant pool
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    // Offset of the first instruction of every line and the line's position, sorted by offset
+    pub lines: Vec<(usize, Position)>,
+    // Strings and identifiers used by the code
+    pub constants: Vec<String>,
+}
+
+// Operands are read at an offset of the code, which is validated beforehand
+impl Chunk {
+    pub fn read_u8(&self, offset: usize) -> u8 {
+        self.code[offset]
+    }
+
+    pub fn read_u32(&self, offset: usize) -> usize {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.code[offset..offset + 4]);
+        u32::from_le_bytes(bytes) as usize
+    }
+
+    pub fn read_i32(&self, offset: usize) -> i32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.code[offset..offset + 4]);
+        i32::from_le_bytes(bytes)
+    }
+
+    pub fn read_f64(&self, offset: usize) -> f64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.code[offset..offset + 8]);
+        f64::from_le_bytes(bytes)
+    }
+
+    // Constant referenced by the index at offset
+    pub fn constant(&self, offset: usize) -> &str {
+        &self.constants[self.read_u32(offset)]
+    }
+
+    // Position of the line containing the instruction at offset
+    pub fn position_at(&self, offset: usize) -> Option<&Position> {
+        let index = self.lines.partition_point(|(start, _)| *start <= offset);
+        if index == 0 {
+            return None;
+        }
+        Some(&self.lines[index - 1].1)
+    }
+}
+
+// While or for loop being compiled
+struct Loop {
+    // Start of the condition (or of the call to next()), where `continue` jumps to
+    start: usize,
+    // Scopes which were open before the loop
+    scope_depth: usize,
+    // Positions of the jump offsets of every `break`
+    breaks: Vec<usize>,
+}
 
 pub struct ByteCode {
-    pub commands: HashMap<String, u8>,
-    pub brackets_opened: i32,
-    pub functions: HashMap<i32, String>
+    pub code: Vec<u8>,
+    pub lines: Vec<(usize, Position)>,
+    pub constants: Vec<String>,
+    // Index of every constant, so each one is stored once
+    constant_indexes: HashMap<String, usize>,
+    pub errors: Vec<Diagnostic>,
+    // Loops around the current statement, innermost last, within the current function
+    loops: Vec<Loop>,
+    // Scopes opened within the current function
+    scope_depth: usize,
+    // Superclass of the class being defined, used by `super`
+    superclass: Option<String>,
 }
 
 impl ByteCode {
     pub fn new() -> ByteCode {
-        let mut commands: HashMap<String, u8> = HashMap::new();
-        commands.insert(String::from(";"), 0);
-        commands.insert(String::from("get"), 1);
-        commands.insert(String::from("set_get"), 2);
-        commands.insert(String::from("set_get_num"), 3);
-        commands.insert(String::from("push"), 4);
-        commands.insert(String::from("push_num"), 5);
-        commands.insert(String::from("push_str"), 6);
-        commands.insert(String::from("+"), 7);
-        commands.insert(String::from("-"), 8);
-        commands.insert(String::from("*"), 9);
-        commands.insert(String::from("/"), 10);
-        commands.insert(String::from("="), 11);
-        commands.insert(String::from("call"), 21);
-        commands.insert(String::from("fun_start"), 22);
-        commands.insert(String::from("fun_end"), 23);
-        commands.insert(String::from("scope_new"), 24);
-        commands.insert(String::from("scope_end"), 25);
-        commands.insert(String::from("arg"), 26);
-        commands.insert(String::from("arg_type"), 27);
-        commands.insert(String::from("push_bool"), 28);
-        commands.insert(String::from("or"), 29);
-        commands.insert(String::from("xor"), 30);
-        commands.insert(String::from("and"), 31);
-        commands.insert(String::from("!"), 32);
-        commands.insert(String::from("if"), 33);
-        commands.insert(String::from("while"), 34);
-        commands.insert(String::from("condition"), 35);
-        commands.insert(String::from("close"), 36);
-        commands.insert(String::from("<"), 37);
-        commands.insert(String::from(">"), 38);
-        commands.insert(String::from("<="), 39);
-        commands.insert(String::from(">="), 40);
-        commands.insert(String::from("=="), 41);
-        commands.insert(String::from("!="), 42);
-        commands.insert(String::from("%"), 43);
-        commands.insert(String::from("."), 44);
-        commands.insert(String::from("class"), 45);
-        commands.insert(String::from("return"), 46);
-        commands.insert(String::from("execute"), 47);
-
         ByteCode {
-            commands,
-            brackets_opened: 0,
-            functions: HashMap::new()
+            code: vec![],
+            lines: vec![],
+            constants: vec![],
+            constant_indexes: HashMap::new(),
+            errors: vec![],
+            loops: vec![],
+            scope_depth: 0,
+            superclass: None,
+        }
+    }
+
+    // Compile statements and append them to the code
+    pub fn compile(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
         }
     }
 
-    pub fn generate_bytecode(&mut self, node: Node) -> Vec<u8> {
-        match node.token.token_type {
-            TokenType::Identifier => {
-                // Function
-                return if node.invoke {
-                    let mut output = vec![];
+    // Take compiled code with its line table and constants
+    pub fn take_chunk(&mut self) -> Chunk {
+        self.constant_indexes.clear();
+        Chunk {
+            code: std::mem::take(&mut self.code),
+            lines: std::mem::take(&mut self.lines),
+            constants: std::mem::take(&mut self.constants),
+        }
+    }
 
-                    // Get function
-                    output.push(self.commands["get"]);
-                    for ch in node.token.value.chars() {
-                        output.push(ch as u8);
-                    }
-                    output.push(0);
+    // Start a new entry of the line table at the end of the code
+    fn mark(&mut self, position: &Position) {
+        if self.lines.last().is_some_and(|(start, _)| *start == self.code.len()) {
+            self.lines.pop();
+        }
+        self.lines.push((self.code.len(), position.clone()));
+    }
 
-                    for child in &node.children {
-                        let bytecode = self.generate_bytecode(child.clone());
-                        for byte in bytecode {
-                            output.push(byte);
-                        }
-                        output.push(0);
-                    }
+    fn emit(&mut self, opcode: OpCode) {
+        self.code.push(opcode as u8);
+    }
 
-                    // Call function
-                    output.push(self.commands["call"]);
-                    output.push(node.children.len() as u8);
-                    output.push(0);
-
-                    output
-                // Variable
-                } else {
-                    let mut output = vec![];
-                    output.push(self.commands["get"]);
-                    for ch in node.token.value.chars() {
-                        output.push(ch as u8);
-                    }
-                    output.push(0);
+    // Instruction with a constant as its operand
+    fn emit_constant(&mut self, opcode: OpCode, value: &str) {
+        self.emit(opcode);
+        self.push_constant(value);
+    }
+
+    // Add index of the constant to the code, adding it to the pool if it is new
+    fn push_constant(&mut self, value: &str) {
+        let index = match self.constant_indexes.get(value) {
+            Some(index) => *index,
+            None => {
+                self.constants.push(value.to_string());
+                self.constant_indexes.insert(value.to_string(), self.constants.len() - 1);
+                self.constants.len() - 1
+            }
+        };
+        self.code.extend_from_slice(&(index as u32).to_le_bytes());
+    }
+
+    // Reserve space for a jump offset, returns its position
+    fn push_offset(&mut self) -> usize {
+        let position = self.code.len();
+        self.code.extend_from_slice(&[0; OFFSET_SIZE]);
+        position
+    }
+
+    // Point a reserved forward offset at the end of the code
+    fn patch_offset(&mut self, position: usize) {
+        let offset = (self.code.len() - position - OFFSET_SIZE) as u32;
+        self.code[position..position + OFFSET_SIZE].copy_from_slice(&offset.to_le_bytes());
+    }
 
-                    output
+    // Jump back to start
+    fn emit_loop(&mut self, start: usize) {
+        self.emit(OpCode::Loop);
+        let end = self.code.len() + OFFSET_SIZE;
+        self.code.extend_from_slice(&((end - start) as u32).to_le_bytes());
+    }
+
+    fn open_scope(&mut self) {
+        self.emit(OpCode::ScopeNew);
+        self.scope_depth += 1;
+    }
+
+    fn close_scope(&mut self) {
+        self.emit(OpCode::ScopeEnd);
+        self.scope_depth -= 1;
+    }
+
+    // Close the scopes opened inside of the innermost loop, including the one of its body, before `break` or
+    // `continue` jumps out of them. Reports the statement if it is outside of any loop.
+    fn leave_loop_scopes(&mut self, keyword: &str, position: &Position) -> bool {
+        let scope_depth = match self.loops.last() {
+            Some(enclosing) => enclosing.scope_depth,
+            None => {
+                self.errors.push(Diagnostic::new(position.clone(), format!("'{}' outside of a loop", keyword)));
+                return false;
+            }
+        };
+        for _ in scope_depth..self.scope_depth {
+            self.emit(OpCode::ScopeEnd);
+        }
+        true
+    }
+
+    // Statements of a block, the code closing it belongs to the '}'
+    fn block(&mut self, block: &Block) {
+        self.compile(&block.body);
+        self.mark(&block.end);
+    }
+
+    // Statements of a class body. Fields are null in the class itself, their initializers are compiled into the
+    // `$fields` method, which computes them for every new instance before `init` runs.
+    fn class_body(&mut self, block: &Block) {
+        let mut initializers = vec![];
+        for statement in &block.body {
+            match statement {
+                Stmt::Let { name, value, position } => {
+                    self.mark(position);
+                    self.emit(OpCode::PushNull);
+                    self.emit_constant(OpCode::Define, name);
+                    if let Some(value) = value {
+                        initializers.push((name, value, position));
+                    }
                 }
+                statement => self.statement(statement),
+            }
+        }
+
+        if !initializers.is_empty() {
+            self.emit_constant(OpCode::FunStart, "$fields");
+            self.code.push(0);
+            let offset = self.push_offset();
+            let scope_depth = std::mem::replace(&mut self.scope_depth, 0);
+            for (name, value, position) in initializers {
+                self.mark(position);
+                self.emit_constant(OpCode::Get, "self");
+                self.expression(value);
+                self.emit_constant(OpCode::SetMember, name);
+                self.emit(OpCode::Pop);
+            }
+            self.scope_depth = scope_depth;
+            self.mark(&block.end);
+            self.emit_constant(OpCode::FunEnd, "$fields");
+            self.patch_offset(offset);
+        }
+        self.mark(&block.end);
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        self.mark(statement.position());
+        match statement {
+            Stmt::Expression(expression) => {
+                self.expression(expression);
+                self.emit(OpCode::Pop);
             }
-            TokenType::NumberLiteral => {
-                let mut output = vec![];
-                output.push(self.commands["push_num"]);
-                for ch in node.token.value.chars() {
-                    output.push(ch as u8);
+            Stmt::Let { name, value, .. } => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => self.emit(OpCode::PushNull),
                 }
-                output.push(0);
-                return output
+                self.emit_constant(OpCode::Define, name);
             }
-            TokenType::StringLiteral => {
-                let mut output = vec![];
-                output.push(self.commands["push_str"]);
-                for ch in node.token.value.chars() {
-                    output.push(ch as u8);
+            Stmt::Function { name, params, body, .. } => {
+                self.emit_constant(OpCode::FunStart, name);
+                self.code.push(params.len() as u8);
+                // Length of the body, so the definition can jump over it
+                let position = self.push_offset();
+
+                for param in params {
+                    self.emit_constant(OpCode::Arg, &param.name);
+                    self.emit_constant(OpCode::ArgType, &param.type_name);
                 }
-                output.push(0);
-                return output;
+                // Loops around the definition cannot be left from inside of the function
+                let loops = std::mem::take(&mut self.loops);
+                let scope_depth = std::mem::replace(&mut self.scope_depth, 0);
+                self.block(body);
+                self.loops = loops;
+                self.scope_depth = scope_depth;
+
+                self.emit_constant(OpCode::FunEnd, name);
+                self.patch_offset(position);
             }
-            TokenType::BooleanLiteral => {
-                let mut output = vec![];
-                output.push(self.commands["push_bool"]);
-                output.push(if node.token.value == "true" {'1' as u8} else {'0' as u8});
-                output.push(0);
-                return output;
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                // Number of returned values (0 or 1)
+                self.emit(OpCode::Return);
+                self.code.push(value.is_some() as u8);
             }
-            TokenType::Operator => {
-                return if node.token.value == "!" {
-                    let mut output = vec![];
-                    output.append(
-                        &mut self.generate_bytecode(node.children[0].clone())
-                    );
-                    output.push(self.commands[&node.token.value]);
-                    output.push(0);
-                    output
-                } else if node.token.value == "." {
-                    let mut output = vec![];
-                    output.append(
-                        &mut self.generate_bytecode(node.children[0].clone())
-                    );
-                    output.push(self.commands[&node.token.value]);
-                    for ch in node.children[1].token.value.chars() {
-                        output.push(ch as u8);
-                    }
-                    output.push(0);
-
-                    // Is a function
-                    if node.children[1].invoke {
-                        for child in &node.children[1].children {
-                            let bytecode = self.generate_bytecode(child.clone());
-                            for byte in bytecode {
-                                output.push(byte);
+            Stmt::If { condition, body, otherwise, .. } => {
+                self.expression(condition);
+                self.emit(OpCode::JumpIfFalse);
+                let position = self.push_offset();
+                self.open_scope();
+                self.block(body);
+                self.close_scope();
+
+                match otherwise {
+                    None => self.patch_offset(position),
+                    Some(otherwise) => {
+                        // Skip the else branch after running the body
+                        self.emit(OpCode::Jump);
+                        let end = self.push_offset();
+                        self.patch_offset(position);
+                        match otherwise {
+                            Else::Block(block) => {
+                                self.open_scope();
+                                self.block(block);
+                                self.close_scope();
                             }
-                            output.push(0);
+                            Else::If(statement) => self.statement(statement),
                         }
-
-                        // Call function
-                        output.push(self.commands["call"]);
-                        output.push(node.children[1].children.len() as u8);
-                        output.push(0);
+                        self.patch_offset(end);
                     }
-                    output
-                } else {
-                    let mut output = vec![];
-                    output.append(
-                        &mut self.generate_bytecode(node.children[0].clone())
-                    );
-                    output.append(
-                        &mut self.generate_bytecode(node.children[1].clone())
-                    );
-                    output.push(self.commands[&node.token.value]);
-                    output.push(0);
-                    output
                 }
             }
-            TokenType::Keyword => {
-                if Keywords::new().list.contains(&*node.token.value) {
-                    match node.token.value.as_str(){
-                        "let" => {
-                            let mut output = vec![];
-                            output.append(
-                                &mut self.generate_bytecode(node.children[0].clone())
-                            );
-                            output[0] = self.commands["set_get"];
-                            return output;
-                        },
-                        "function" => {
-                            let mut output = vec![];
-                            output.push(self.commands["fun_start"]);
-                            for ch in node.children[0].token.value.chars() {
-                                output.push(ch as u8);
-                            }
-                            self.functions.insert(
-                                self.brackets_opened.clone()+1,
-                                node.children[0].token.value.clone()
-                            );
-                            output.push(0);
-
-                            // Arguments
-                            for child in &node.children[0].children {
-                                if child.token.value == ":" {
-                                    // Add argument name to the bytecode
-                                    output.push(self.commands["arg"]);
-                                    for ch in child.children[0].token.value.chars() {
-                                        output.push(ch as u8);
-                                    }
-                                    output.push(0);
-
-                                    // Add argument type to the bytecode
-                                    output.push(self.commands["arg_type"]);
-                                    for ch in child.children[1].token.value.chars() {
-                                        output.push(ch as u8);
-                                    }
-                                    output.push(0);
-                                } else {
-                                    println!(
-                                        "Error at argument declaration in '{}'. \
-                                         Expected ':' operator", node.children[0].token.value
-                                    );
-                                    process::exit(1);
-                                }
-                            }
-                            return output;
-                        },
-                        "if" => {
-                            let mut output = vec![];
-                            output.append(
-                                &mut self.generate_bytecode(node.children[0].clone())
-                            );
-                            output.push(self.commands["if"]);
-                            output.push(0);
-                            return output;
-                        }
-                        "while" => {
-                            let mut output = vec![];
-                            output.push(self.commands["condition"]);
-                            output.push(0);
-                            output.append(
-                                &mut self.generate_bytecode(node.children[0].clone())
-                            );
-                            output.push(self.commands["while"]);
-                            output.push(0);
-                            return output;
-                        }
-                        "return" => {
-                            let mut output = vec![];
-                            output.append(
-                                &mut self.generate_bytecode(node.children[0].clone())
-                            );
-                            output.push(0);
-                            output.push(self.commands["return"]);
-                            output.push(0);
-                            return output;
-                        }
-                        "class" => {
-                            let mut output = vec![];
-                            output.push(self.commands["class"]);
-                            for ch in node.children[0].token.value.chars() {
-                                output.push(ch as u8);
-                            }
-                            output.push(0);
-                            return output;
-                        }
-                        "execute" => {
-                            let mut output = vec![];
-                            output.push(self.commands["execute"]);
-                            for ch in node.children[0].token.value.chars() {
-                                output.push(ch as u8);
-                            }
-                            output.push(0);
-                            return output;
-                        }
-                        _ => {}
-                    }
-                } else {
-                    println!("{} not recognized", node.token.value);
+            Stmt::While { condition, body, .. } => {
+                let start = self.code.len();
+                self.expression(condition);
+                self.emit(OpCode::JumpIfFalse);
+                let position = self.push_offset();
+                self.loops.push(Loop { start, scope_depth: self.scope_depth, breaks: vec![] });
+                self.open_scope();
+                self.block(body);
+                self.close_scope();
+
+                // Jump back to the condition
+                self.emit_loop(start);
+                self.patch_offset(position);
+                for position in self.loops.pop().unwrap().breaks {
+                    self.patch_offset(position);
                 }
             }
-            TokenType::BracketOpen => {
-                self.brackets_opened += 1;
+            Stmt::For { variable, iterable, body, .. } => {
+                // Scope of the iterator and the loop variable, the iterator is named after its offset so nested
+                // loops do not share it
+                self.open_scope();
+                let iterator = format!("$iterator{}", self.code.len());
+                self.expression(iterable);
+                self.emit_constant(OpCode::Member, "iter");
+                self.emit(OpCode::Call);
+                self.code.push(0);
+                self.emit_constant(OpCode::Define, &iterator);
+
+                let start = self.code.len();
+                self.emit_constant(OpCode::Get, &iterator);
+                self.emit_constant(OpCode::Member, "next");
+                self.emit(OpCode::Call);
+                self.code.push(0);
+                self.emit_constant(OpCode::ForNext, variable);
+                let position = self.push_offset();
+
+                self.loops.push(Loop { start, scope_depth: self.scope_depth, breaks: vec![] });
+                self.open_scope();
+                self.block(body);
+                self.close_scope();
+
+                self.emit_loop(start);
+                self.patch_offset(position);
+                for position in self.loops.pop().unwrap().breaks {
+                    self.patch_offset(position);
+                }
+                self.close_scope();
             }
-            TokenType::BracketClose => {
-                let mut output = vec![];
-                if self.functions.contains_key(&self.brackets_opened) {
-                    output.push(self.commands["fun_end"]);
-                    for ch in self.functions[&self.brackets_opened].clone().chars() {
-                        output.push(ch as u8);
-                    }
-                    output.push(0);
+            Stmt::Break(position) => {
+                if self.leave_loop_scopes("break", position) {
+                    self.emit(OpCode::Jump);
+                    let offset = self.push_offset();
+                    self.loops.last_mut().unwrap().breaks.push(offset);
+                }
+            }
+            Stmt::Continue(position) => {
+                if self.leave_loop_scopes("continue", position) {
+                    self.emit_loop(self.loops.last().unwrap().start);
+                }
+            }
+            Stmt::Class { name, superclass, body, .. } => {
+                // The body is not part of any loop around the class
+                let loops = std::mem::take(&mut self.loops);
+                self.emit_constant(OpCode::Class, name);
+                if let Some(superclass) = superclass {
+                    self.emit_constant(OpCode::Get, superclass);
+                    self.emit(OpCode::Inherit);
+                }
+                let enclosing_superclass = std::mem::replace(&mut self.superclass, superclass.clone());
+                self.class_body(body);
+                self.emit(OpCode::Close);
+                self.superclass = enclosing_superclass;
+                self.loops = loops;
+            }
+            Stmt::Execute { file, .. } => self.emit_constant(OpCode::Execute, file),
+            Stmt::Block(body, _) => {
+                self.open_scope();
+                self.block(body);
+                self.close_scope();
+            }
+        }
+    }
 
-                    self.functions.remove(&self.brackets_opened);
-                    self.brackets_opened -= 1;
-                } else {
-                    output.push(self.commands["close"]);
-                    output.push(0);
+    fn expression(&mut self, expression: &Expr) {
+        match expression {
+            Expr::Int(value, _) => {
+                self.emit(OpCode::PushInt);
+                self.code.extend_from_slice(&value.to_le_bytes());
+            }
+            Expr::Float(value, _) => {
+                self.emit(OpCode::PushFloat);
+                self.code.extend_from_slice(&value.to_le_bytes());
+            }
+            Expr::String(value, _) => self.emit_constant(OpCode::PushStr, value),
+            Expr::Boolean(value, _) => {
+                self.emit(OpCode::PushBool);
+                self.code.push(*value as u8);
+            }
+            Expr::Variable(name, _) => self.emit_constant(OpCode::Get, name),
+            Expr::Binary { operator, left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(binary_opcode(*operator));
+            }
+            Expr::Unary { operator, operand, .. } => {
+                self.expression(operand);
+                // '+' leaves the value as it is
+                match operator {
+                    UnaryOperator::Neg => self.emit(OpCode::Neg),
+                    UnaryOperator::Not => self.emit(OpCode::Not),
+                    UnaryOperator::Plus => {}
+                }
+            }
+            Expr::Assign { target, value, .. } => match target.as_ref() {
+                Expr::Variable(name, _) => {
+                    self.expression(value);
+                    self.emit_constant(OpCode::Set, name);
+                }
+                Expr::Member { object, name, .. } => {
+                    self.expression(object);
+                    self.expression(value);
+                    self.emit_constant(OpCode::SetMember, name);
+                }
+                // Rejected by the parser already
+                target => {
+                    self.errors.push(Diagnostic::new(
+                        target.position().clone(),
+                        String::from("Cannot assign to this expression")
+                    ));
+                }
+            }
+            Expr::Call { callee, arguments, .. } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.emit(OpCode::Call);
+                self.code.push(arguments.len() as u8);
+            }
+            Expr::Member { object, name, .. } => {
+                self.expression(object);
+                self.emit_constant(OpCode::Member, name);
+            }
+            Expr::Super { name, position } => match self.superclass.clone() {
+                Some(superclass) => {
+                    self.emit_constant(OpCode::Get, "self");
+                    self.emit_constant(OpCode::Get, &superclass);
+                    self.emit_constant(OpCode::Super, name);
+                }
+                None => {
+                    self.errors.push(Diagnostic::new(position.clone(), String::from("'super' outside of a subclass")));
                 }
-                self.brackets_opened -= 1;
-                return output;
             }
-            _ => {}
         }
-        return vec![0]
+    }
+}
+
+fn binary_opcode(operator: BinaryOperator) -> OpCode {
+    match operator {
+        BinaryOperator::Add => OpCode::Add,
+        BinaryOperator::Sub => OpCode::Sub,
+        BinaryOperator::Mul => OpCode::Mul,
+        BinaryOperator::Div => OpCode::Div,
+        BinaryOperator::Mod => OpCode::Mod,
+        BinaryOperator::Less => OpCode::Less,
+        BinaryOperator::LessEqual => OpCode::LessEqual,
+        BinaryOperator::Greater => OpCode::Greater,
+        BinaryOperator::GreaterEqual => OpCode::GreaterEqual,
+        BinaryOperator::Equal => OpCode::Equal,
+        BinaryOperator::NotEqual => OpCode::NotEqual,
+        BinaryOperator::And => OpCode::And,
+        BinaryOperator::Or => OpCode::Or,
+        BinaryOperator::Xor => OpCode::Xor,
+        BinaryOperator::Range => OpCode::Range,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+
+    fn compile(code: &str) -> ByteCode {
+        let tokens = tokenize(code.to_string(), "test.yapko").unwrap();
+        let program = Parser::new().parse_program(tokens).map_err(|errors| errors[0].message.clone()).unwrap();
+        let mut bytecode = ByteCode::new();
+        bytecode.compile(&program);
+        bytecode
+    }
+
+    fn errors(code: &str) -> Vec<String> {
+        compile(code).errors.iter().map(|error| format!("{} {}", error.position.line, error.message)).collect()
+    }
+
+    // Instructions without their operands
+    fn opcodes(code: &str) -> Vec<OpCode> {
+        let bytecode = compile(code);
+        let mut opcodes = vec![];
+        let mut i = 0;
+        while i < bytecode.code.len() {
+            let opcode = OpCode::try_from(bytecode.code[i]).unwrap();
+            opcodes.push(opcode);
+            i += 1 + opcode.operand_size();
+        }
+        opcodes
+    }
+
+    // Every jump with the offsets of its instruction and of the instruction it leads to
+    fn jumps(code: &str) -> Vec<(usize, OpCode, usize)> {
+        let code = compile(code).code;
+        let mut jumps = vec![];
+        let mut i = 0;
+        while i < code.len() {
+            let opcode = OpCode::try_from(code[i]).unwrap();
+            let end = i + 1 + opcode.operand_size();
+            if matches!(opcode, OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop) {
+                let offset = u32::from_le_bytes(code[i + 1..end].try_into().unwrap()) as usize;
+                let target = if opcode == OpCode::Loop { end - offset } else { end + offset };
+                jumps.push((i, opcode, target));
+            }
+            i = end;
+        }
+        jumps
+    }
+
+    #[test]
+    fn loops_and_branches_compile_to_jumps() {
+        use OpCode::*;
+        // The false condition leaves the loop, whose end jumps back to the condition
+        let code = "while a {\n b\n}";
+        assert_eq!(opcodes(code), vec![Get, JumpIfFalse, ScopeNew, Get, Pop, ScopeEnd, Loop]);
+        assert_eq!(compile(code).code.len(), 23);
+        assert_eq!(jumps(code), vec![(5, JumpIfFalse, 23), (18, Loop, 0)]);
+
+        // The false condition skips the body, the end of the body skips the else branch
+        let code = "if a {\n b\n} else {\n c\n}";
+        assert_eq!(opcodes(code), vec![
+            Get, JumpIfFalse, ScopeNew, Get, Pop, ScopeEnd, Jump,
+            ScopeNew, Get, Pop, ScopeEnd
+        ]);
+        assert_eq!(compile(code).code.len(), 31);
+        assert_eq!(jumps(code), vec![(5, JumpIfFalse, 23), (18, Jump, 31)]);
+    }
+
+    #[test]
+    fn break_and_continue_outside_of_loops_are_errors() {
+        assert_eq!(errors("break\ncontinue"), vec!["1 'break' outside of a loop", "2 'continue' outside of a loop"]);
+        // Functions and classes defined in a loop cannot leave it
+        assert_eq!(
+            errors("while true {\n function f() {\n  break\n }\n class C {\n  continue\n }\n}"),
+            vec!["3 'break' outside of a loop", "6 'continue' outside of a loop"]
+        );
+        assert!(errors("while true {\n function f() {\n  while true { break }\n }\n continue\n}").is_empty());
+    }
+
+    #[test]
+    fn super_needs_a_superclass() {
+        assert_eq!(
+            errors("super.f()\nclass A {\n function f() {\n  return super.f()\n }\n}"),
+            vec!["1 'super' outside of a subclass", "4 'super' outside of a subclass"]
+        );
+        assert!(errors("class B : A {\n function f() {\n  return super.f()\n }\n}").is_empty());
+    }
+
+    #[test]
+    fn break_and_continue_close_the_scopes_of_the_loop() {
+        use OpCode::*;
+        assert_eq!(opcodes("while a {\n if b { break }\n}"), vec![
+            Get, JumpIfFalse, ScopeNew,
+            Get, JumpIfFalse, ScopeNew, ScopeEnd, ScopeEnd, Jump, ScopeEnd,
+            ScopeEnd, Loop
+        ]);
+        // The scope of the iterator is left after the loop, where break jumps to
+        assert_eq!(opcodes("for x in a {\n break\n}"), vec![
+            ScopeNew, Get, Member, Call, Define,
+            Get, Member, Call, ForNext, ScopeNew, ScopeEnd, Jump, ScopeEnd, Loop,
+            ScopeEnd
+        ]);
+        assert_eq!(opcodes("while a {\n while b { continue }\n}"), vec![
+            Get, JumpIfFalse, ScopeNew,
+            Get, JumpIfFalse, ScopeNew, ScopeEnd, Loop, ScopeEnd, Loop,
+            ScopeEnd, Loop
+        ]);
     }
 }
@@ -0,0 +1,112 @@
+use std::fmt;
+
+// Place in the source code
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Position {
+    pub file: String,
+    // Line and column start at 1
+    pub line: usize,
+    pub column: usize,
+    // Byte span in the file
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Position {
+    // Position spanning from the start of self to the end of other
+    pub fn to(&self, other: &Position) -> Position {
+        Position {
+            end: other.end.max(self.end),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+// Error pointing at a place in the source code
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub position: Position,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(position: Position, message: String) -> Diagnostic {
+        Diagnostic {
+            position,
+            message,
+        }
+    }
+
+    // Format error with the offending source line and a caret underline
+    pub fn render(&self, source: &str) -> String {
+        let mut output = format!("{}: error: {}", self.position, self.message);
+
+        let line = match source.lines().nth(self.position.line.saturating_sub(1)) {
+            Some(line) => line,
+            None => return output
+        };
+        let line_number = self.position.line.to_string();
+        let margin = " ".repeat(line_number.len());
+
+        // Keep tabs so the caret lines up with the source line
+        let padding: String = line.chars()
+            .take(self.position.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let length = source.get(self.position.start..self.position.end)
+            .map(|span| span.lines().next().unwrap_or("").chars().count())
+            .unwrap_or(1)
+            .max(1);
+
+        output += &format!("\n{} |\n{} | {}\n{} | {}{}", margin, line_number, line, margin, padding, "^".repeat(length));
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: usize, column: usize, start: usize, end: usize) -> Diagnostic {
+        let position = Position { file: String::from("test.yapko"), line, column, start, end };
+        Diagnostic::new(position, String::from("Unexpected token"))
+    }
+
+    #[test]
+    fn caret_underlines_the_span_on_its_line() {
+        let source = "let a = 1\nlet b = a +\n";
+        assert_eq!(
+            diagnostic(2, 9, 18, 21).render(source),
+            "test.yapko:2:9: error: Unexpected token\n  |\n2 | let b = a +\n  |         ^^^"
+        );
+        // Empty span still gets a caret, spans over several lines are underlined on the first one
+        assert_eq!(
+            diagnostic(1, 5, 4, 4).render(source),
+            "test.yapko:1:5: error: Unexpected token\n  |\n1 | let a = 1\n  |     ^"
+        );
+        assert_eq!(
+            diagnostic(1, 9, 8, 20).render(source),
+            "test.yapko:1:9: error: Unexpected token\n  |\n1 | let a = 1\n  |         ^"
+        );
+    }
+
+    #[test]
+    fn margin_and_tabs_line_up_with_the_source() {
+        let source = format!("{}\tlet x = y", "\n".repeat(9));
+        assert_eq!(
+            diagnostic(10, 10, 18, 19).render(&source),
+            "test.yapko:10:10: error: Unexpected token\n   |\n10 | \tlet x = y\n   | \t        ^"
+        );
+    }
+
+    #[test]
+    fn line_outside_of_the_source_is_not_shown() {
+        assert_eq!(diagnostic(3, 1, 20, 21).render("let a = 1\n"), "test.yapko:3:1: error: Unexpected token");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::bytecode::{Chunk, CONSTANT_SIZE, OpCode};
+
+// Print the constant pool, then one instruction per line: offset, mnemonic and decoded operand.
+// Every source line is printed before its first instruction when the source is given.
+pub fn disassemble(chunk: &Chunk, source: Option<&str>) -> String {
+    let code = &chunk.code;
+    let source_lines: Vec<&str> = source.map_or(vec![], |source| source.lines().collect());
+    let mut next_line = 0;
+    let mut printed_line = 0;
+    let mut output = String::new();
+
+    for (index, constant) in chunk.constants.iter().enumerate() {
+        output += &format!("#{:<5} {:?}\n", index, constant);
+    }
+
+    let mut i = 0;
+    while i < code.len() {
+        let instruction_start = i;
+        let opcode = OpCode::try_from(code[i]);
+        i += 1;
+        // Separator between instructions
+        if opcode == Ok(OpCode::Nop) {
+            continue;
+        }
+
+        // Source line starting at this instruction, once even if several statements share it (e.g. `} else {`)
+        while next_line < chunk.lines.len() && chunk.lines[next_line].0 <= instruction_start {
+            let position = &chunk.lines[next_line].1;
+            if position.line != printed_line {
+                let text = source_lines.get(position.line.saturating_sub(1)).map_or("", |line| line.trim());
+                output += &format!("{:>4} | {}\n", position.line, text);
+                printed_line = position.line;
+            }
+            next_line += 1;
+        }
+
+        let opcode = match opcode {
+            Ok(opcode) => opcode,
+            Err(byte) => {
+                output += &format!("{:06}  unknown opcode {}\n", instruction_start, byte);
+                continue;
+            }
+        };
+        if code.len() - i < opcode.operand_size() {
+            output += &format!("{:06}  {:<14}<truncated>\n", instruction_start, opcode.to_string());
+            break;
+        }
+
+        let operand = i;
+        i += opcode.operand_size();
+        let operand = match opcode {
+            OpCode::FunStart => {
+                let parameter_count = chunk.read_u8(operand + CONSTANT_SIZE);
+                let length = chunk.read_u32(operand + CONSTANT_SIZE + 1);
+                format!(
+                    "{} ({} parameter(s), body {} bytes, ends at {:06})",
+                    chunk.constant(operand), parameter_count, length, i + length
+                )
+            }
+            OpCode::ForNext => {
+                let offset = chunk.read_u32(operand + CONSTANT_SIZE);
+                format!("{} (done: +{} -> {:06})", chunk.constant(operand), offset, i + offset)
+            }
+            OpCode::Jump | OpCode::JumpIfFalse => {
+                let offset = chunk.read_u32(operand);
+                format!("+{} -> {:06}", offset, i + offset)
+            }
+            OpCode::Loop => {
+                let offset = chunk.read_u32(operand);
+                format!("-{} -> {:06}", offset, i.saturating_sub(offset))
+            }
+            OpCode::PushStr => format!("{:?}", chunk.constant(operand)),
+            _ if opcode.has_constant() => chunk.constant(operand).to_string(),
+            OpCode::PushInt => chunk.read_i32(operand).to_string(),
+            OpCode::PushFloat => format!("{:?}", chunk.read_f64(operand)),
+            OpCode::PushBool => (chunk.read_u8(operand) != 0).to_string(),
+            OpCode::Call => format!("{} argument(s)", chunk.read_u8(operand)),
+            OpCode::Return => format!("{} value(s)", chunk.read_u8(operand)),
+            _ => String::new()
+        };
+        output += format!("{:06}  {:<14}{}", instruction_start, opcode.to_string(), operand).trim_end();
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::ByteCode;
+    use crate::compile;
+
+    #[test]
+    fn instructions_are_listed_under_their_source_lines() {
+        let source = "let a = 1.5\nfunction f(n: Int) {\n return n\n}\nwhile true { f(2) }\n";
+        let chunk = compile(source.to_string(), "test.yapko", &mut ByteCode::new()).unwrap();
+        assert_eq!(
+            disassemble(&chunk, Some(source)),
+            "#0     \"a\"\n#1     \"f\"\n#2     \"n\"\n#3     \"Int\"\n\
+             \x20  1 | let a = 1.5\n\
+             000000  push_float    1.5\n\
+             000009  define        a\n\
+             \x20  2 | function f(n: Int) {\n\
+             000014  fun_start     f (1 parameter(s), body 22 bytes, ends at 000046)\n\
+             000024  arg           n\n\
+             000029  arg_type      Int\n\
+             \x20  3 | return n\n\
+             000034  get           n\n\
+             000039  return        1 value(s)\n\
+             \x20  4 | }\n\
+             000041  fun_end       f\n\
+             \x20  5 | while true { f(2) }\n\
+             000046  push_bool     true\n\
+             000048  jump_if_false +20 -> 000073\n\
+             000053  scope_new\n\
+             000054  get           f\n\
+             000059  push_int      2\n\
+             000064  call          1 argument(s)\n\
+             000066  pop\n\
+             000067  scope_end\n\
+             000068  loop          -27 -> 000046\n"
+        );
+        // Without the source only the line numbers are known
+        assert!(disassemble(&chunk, None).contains("   3 | \n000034  get           n\n"));
+    }
+
+    #[test]
+    fn unknown_and_truncated_instructions_are_shown() {
+        let chunk = Chunk { code: vec![255, OpCode::Nop as u8, OpCode::PushInt as u8, 1], ..Chunk::default() };
+        assert_eq!(disassemble(&chunk, None), "000000  unknown opcode 255\n000002  push_int      <truncated>\n");
+    }
+}
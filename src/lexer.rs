@@ -1,10 +1,13 @@
 use std::collections::HashSet;
+use std::num::IntErrorKind;
 use strum_macros::Display;
+use crate::diagnostic::{Diagnostic, Position};
 
 #[derive(Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub value: String,
+    pub position: Position,
 }
 
 #[derive(Clone, Display)]
@@ -20,7 +23,7 @@ pub enum TokenType {
     BracketClose,
     End,
     Keyword,
-    NONE
+    None
 }
 
 pub struct Keywords {
@@ -34,8 +37,14 @@ impl Keywords {
         list.insert(String::from("function"));
         list.insert(String::from("return"));
         list.insert(String::from("if"));
+        list.insert(String::from("else"));
         list.insert(String::from("while"));
+        list.insert(String::from("for"));
+        list.insert(String::from("in"));
+        list.insert(String::from("break"));
+        list.insert(String::from("continue"));
         list.insert(String::from("class"));
+        list.insert(String::from("super"));
         list.insert(String::from("execute"));
 
         Keywords {
@@ -44,159 +53,476 @@ impl Keywords {
     }
 }
 
-pub(crate) fn tokenize(code: String) -> Vec<Token> {
+// Split code into tokens, returns every malformed string or comment on failure
+pub(crate) fn tokenize(code: String, file: &str) -> Result<Vec<Token>, Vec<Diagnostic>> {
     let mut output = vec![];
+    let mut errors = vec![];
     let mut current = String::new();
+    let mut current_position = Position::default();
     let mut string = String::new();
-    let mut last = ' ';
+    let mut string_position = Position::default();
+
+    let mut line = 1;
+    let mut column = 1;
 
     let mut string_literal_start = false;
-    for character in code.chars() {
+    // String started with '"""', it has no escape sequences and may span lines
+    let mut raw_string = false;
+    // Inside of a '//' comment
+    let mut line_comment = false;
+    // Number of '/*' not closed yet, block comments can be nested
+    let mut comment_depth = 0;
+    let mut comment_position = Position::default();
+    // Number of following characters which were already handled (e.g. second character of '//')
+    let mut skip = 0;
+    for (offset, character) in code.char_indices() {
+        let position = Position {
+            file: file.to_string(),
+            line,
+            column,
+            start: offset,
+            end: offset + character.len_utf8(),
+        };
+        if character == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let next = code[position.end..].chars().next();
+
         let mut single_character_token_present = true;
         if string_literal_start {
-            if character == '"' {
-                output.push(Token {
-                    token_type: TokenType::StringLiteral,
-                    value: string.clone()
-                });
-                string_literal_start = false;
-                current.clear();
+            if raw_string {
+                if code[offset..].starts_with("\"\"\"") {
+                    let end = Position { end: offset + 3, ..position.clone() };
+                    output.push(Token {
+                        token_type: TokenType::StringLiteral,
+                        value: string.clone(),
+                        position: string_position.to(&end)
+                    });
+                    string_literal_start = false;
+                    skip = 2;
+                } else {
+                    string.push(character);
+                }
+                continue
+            }
+            match character {
+                '"' => {
+                    output.push(Token {
+                        token_type: TokenType::StringLiteral,
+                        value: string.clone(),
+                        position: string_position.to(&position)
+                    });
+                    string_literal_start = false;
+                    current.clear();
+                    continue
+                }
+                '\\' => {
+                    let rest = &code[position.end..];
+                    let (escaped, length) = read_escape(rest);
+                    skip = length;
+                    match escaped {
+                        Ok(escaped) => string.push(escaped),
+                        Err(message) => {
+                            let length: usize = rest.chars().take(length).map(char::len_utf8).sum();
+                            errors.push(Diagnostic::new(
+                                Position { end: position.end + length, ..position.clone() },
+                                message
+                            ));
+                        }
+                    }
+                    continue
+                }
+                // Only '"""' strings may span lines, report the string and go on with the next line
+                '\n' => {
+                    errors.push(Diagnostic::new(string_position.clone(), String::from("Unterminated string")));
+                    string_literal_start = false;
+                }
+                _ => {
+                    string.push(character);
+                    continue
+                }
+            }
+        }
+        if line_comment {
+            if character != '\n' {
+                continue;
+            }
+            // Newline still ends the line
+            line_comment = false;
+        }
+        if comment_depth > 0 {
+            match (character, next) {
+                ('*', Some('/')) => {
+                    comment_depth -= 1;
+                    skip = 1;
+                }
+                ('/', Some('*')) => {
+                    comment_depth += 1;
+                    skip = 1;
+                }
+                // Comment spanning multiple lines ends them like whitespace would
+                ('\n', _) => {
+                    output.push(Token {
+                        token_type: TokenType::End,
+                        value: character.to_string(),
+                        position: position.clone()
+                    });
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if character == '/' && (next == Some('/') || next == Some('*')) {
+            // Comment separates tokens like whitespace
+            let token = generate_token_from_string(String::from(current.trim()), current_position.clone());
+            if !matches!(token.token_type, TokenType::None) {
+                output.push(token);
+            }
+            current.clear();
+
+            if next == Some('/') {
+                line_comment = true;
             } else {
-                string.push(character);
+                comment_depth += 1;
+                comment_position = Position { end: position.end + 1, ..position.clone() };
             }
-            continue
+            skip = 1;
+            continue;
         }
         match character {
+            // Sign of an exponent, e.g. 1e-5
+            '+'|'-' if is_decimal_number(&current) && current.ends_with(['e', 'E']) => {
+                current.push(character);
+                current_position.end = position.end;
+                single_character_token_present = false;
+            }
             '+'|'-'|'*'|'/'|':'|'!'|'>'|'<'|'%' => {
                 let token = Token {
                     token_type: TokenType::Operator,
-                    value: character.to_string()
+                    value: character.to_string(),
+                    position: position.clone()
                 };
                 output.push(token);
             }
             '=' => {
-                if last == '=' {
-                    output.remove(output.len() - 1);
+                // '==', '<=', '>=' and '!=' when written right after the first character
+                let merges = output.last().is_some_and(|previous: &Token| {
+                    matches!(previous.token_type, TokenType::Operator)
+                        && matches!(previous.value.as_str(), "=" | "<" | ">" | "!")
+                        && previous.position.end == position.start
+                });
+                if merges {
+                    let previous = output.remove(output.len() - 1);
 
                     let token = Token {
                         token_type: TokenType::Operator,
-                        value: "==".to_string()
+                        value: format!("{}=", previous.value),
+                        position: previous.position.to(&position)
                     };
                     output.push(token);
                 } else {
                     let token = Token {
                         token_type: TokenType::Operator,
-                        value: "=".to_string()
+                        value: "=".to_string(),
+                        position: position.clone()
                     };
                     output.push(token);
                 }
             }
             '.' => {
-                if !current.chars().all(|c| char::is_numeric(c)) || current.is_empty() {
+                // Decimal point only when a digit follows, so 1.2.3 is one (malformed) number
+                let decimal_point = current.starts_with(|c: char| c.is_ascii_digit())
+                    && next.is_some_and(|c| c.is_ascii_digit());
+                // '..' of a range when written right after another '.'
+                let merges = output.last().is_some_and(|previous: &Token| {
+                    matches!(previous.token_type, TokenType::Operator)
+                        && previous.value == "."
+                        && previous.position.end == position.start
+                });
+                if merges && current.is_empty() {
+                    let previous = output.remove(output.len() - 1);
+                    output.push(Token {
+                        token_type: TokenType::Operator,
+                        value: String::from(".."),
+                        position: previous.position.to(&position)
+                    });
+                } else if !decimal_point {
                     let token = Token {
                         token_type: TokenType::Operator,
-                        value: character.to_string()
+                        value: character.to_string(),
+                        position: position.clone()
                     };
                     output.push(token);
                 } else {
                     current += ".";
+                    current_position.end = position.end;
                     single_character_token_present = false;
                 }
             }
             '\n'|';' => {
                 let token = Token {
                     token_type: TokenType::End,
-                    value: character.to_string()
+                    value: character.to_string(),
+                    position: position.clone()
                 };
                 output.push(token);
             }
             '(' => {
                 let token = Token {
                     token_type: TokenType::ParenOpen,
-                    value: character.to_string()
+                    value: character.to_string(),
+                    position: position.clone()
                 };
                 output.push(token);
             }
             ')' => {
                 let token = Token {
                     token_type: TokenType::ParenClose,
-                    value: character.to_string()
+                    value: character.to_string(),
+                    position: position.clone()
                 };
                 output.push(token);
             }
             '{' => {
                 output.push(Token {
                     token_type: TokenType::End,
-                    value: "\n".parse().unwrap()
+                    value: "\n".parse().unwrap(),
+                    position: position.clone()
                 });
                 output.push(Token {
                     token_type: TokenType::BracketOpen,
-                    value: character.to_string()
+                    value: character.to_string(),
+                    position: position.clone()
                 });
             }
             '}' => {
                 output.push(Token {
                     token_type: TokenType::BracketClose,
-                    value: character.to_string()
+                    value: character.to_string(),
+                    position: position.clone()
                 });
             }
             '"' => {
                 if !string_literal_start {
                     string.clear();
                     current.clear();
+                    string_position = position.clone();
                     string_literal_start = true;
+                    raw_string = code[offset..].starts_with("\"\"\"");
+                    if raw_string {
+                        skip = 2;
+                    }
                 }
             }
+            // Whitespace only ends the current token, it is not part of its span
+            _ if character.is_whitespace() => {
+                single_character_token_present = false;
+            }
             _ => {
                 single_character_token_present = false;
+                if current.is_empty() {
+                    current_position = position.clone();
+                }
                 current.push(character);
+                current_position.end = position.end;
             }
         };
         if character.is_whitespace() || single_character_token_present {
             let mut index = output.len();
-            if output.len() > 1 {
-                if single_character_token_present {
-                    index = output.len() - 1;
-                }
+            if !output.is_empty() && single_character_token_present {
+                index = output.len() - 1;
             }
 
-            let token = generate_token_from_string(String::from(current.trim()));
-            if !matches!(token.token_type, TokenType::NONE) {
+            let token = generate_token_from_string(String::from(current.trim()), current_position.clone());
+            if !matches!(token.token_type, TokenType::None) {
                 output.insert(index, token);
             }
             current.clear();
         }
-        last = character;
     }
-    return output;
+
+    // Code may not end with a newline
+    let token = generate_token_from_string(String::from(current.trim()), current_position);
+    if !matches!(token.token_type, TokenType::None) && !string_literal_start && comment_depth == 0 {
+        output.push(token);
+    }
+
+    for token in &output {
+        if matches!(token.token_type, TokenType::NumberLiteral) {
+            if let Err(message) = parse_number(&token.value) {
+                errors.push(Diagnostic::new(token.position.clone(), message));
+            }
+        }
+    }
+    if string_literal_start {
+        errors.push(Diagnostic::new(string_position, String::from("Unterminated string")));
+    }
+    if comment_depth > 0 {
+        errors.push(Diagnostic::new(comment_position, String::from("Unterminated comment")));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(output)
+}
+
+// Read escape sequence following a backslash in a string,
+// returns the escaped character (or what is wrong with it) and number of characters read
+fn read_escape(rest: &str) -> (Result<char, String>, usize) {
+    let escaped = match rest.chars().next() {
+        Some('"') => '"',
+        Some('\\') => '\\',
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('0') => '\0',
+        Some('u') => {
+            // \u{...} with 1 to 6 hex digits
+            if !rest[1..].starts_with('{') {
+                return (Err(String::from("Expected '{' after \\u")), 1);
+            }
+            let digits: String = rest[2..].chars().take_while(|c| *c != '}' && *c != '"' && *c != '\n').collect();
+            let length = digits.chars().count() + 2;
+            if !rest[2 + digits.len()..].starts_with('}') {
+                return (Err(String::from("Unterminated unicode escape")), length);
+            }
+            let character = if (1..=6).contains(&digits.len()) {
+                u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+            } else {
+                None
+            };
+            return match character {
+                Some(character) => (Ok(character), length + 1),
+                None => (Err(format!("Invalid unicode escape \\u{{{}}}", digits)), length + 1)
+            };
+        }
+        Some(other) => return (Err(format!("Unknown escape sequence \\{}", other)), 1),
+        None => return (Err(String::from("Unterminated string")), 0)
+    };
+    (Ok(escaped), 1)
+}
+
+// Value of a number literal
+#[derive(Debug, PartialEq)]
+pub enum Number {
+    // Up to MAX_INT_LITERAL, the parser checks that it fits into Int
+    Int(i64),
+    Float(f64),
+}
+
+// Digits of the smallest Int, which only fit into Int after '-'
+pub const MAX_INT_LITERAL: i64 = i32::MAX as i64 + 1;
+
+// Number written with a decimal point or an exponent is a Float, anything else is an Int
+pub fn parse_number(literal: &str) -> Result<Number, String> {
+    let malformed = || format!("Malformed number {}", literal);
+
+    // Underscores may only separate digits
+    let characters: Vec<char> = literal.chars().collect();
+    for (index, character) in characters.iter().enumerate() {
+        if *character == '_' {
+            let separates_digits = characters.get(index + 1).is_some_and(char::is_ascii_alphanumeric)
+                && characters[index - 1].is_ascii_alphanumeric();
+            if !separates_digits {
+                return Err(malformed());
+            }
+        }
+    }
+    let digits = literal.replace('_', "");
+
+    let radix = match digits.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => 10
+    };
+    if radix != 10 {
+        // Reject signs, from_str_radix accepts them
+        if digits[2..].starts_with(['+', '-']) {
+            return Err(malformed());
+        }
+        return match i64::from_str_radix(&digits[2..], radix) {
+            Ok(value) => int_literal(value, literal),
+            Err(error) if *error.kind() == IntErrorKind::PosOverflow => int_literal(i64::MAX, literal),
+            Err(_) => Err(malformed())
+        };
+    }
+
+    if digits.chars().all(|c| c.is_ascii_digit()) {
+        return int_literal(digits.parse::<i64>().unwrap_or(i64::MAX), literal);
+    }
+
+    // digits [. digits] [e [+-] digits]
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(index) => (&digits[..index], Some(&digits[index + 1..])),
+        None => (&digits[..], None)
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None)
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    let valid = is_digits(integer)
+        && fraction.is_none_or(is_digits)
+        && exponent.is_none_or(|exponent| is_digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent)));
+    if !valid {
+        return Err(malformed());
+    }
+    digits.parse::<f64>().map(Number::Float).map_err(|_| malformed())
+}
+
+fn int_literal(value: i64, literal: &str) -> Result<Number, String> {
+    if value > MAX_INT_LITERAL {
+        return Err(format!("Integer {} does not fit into Int", literal));
+    }
+    Ok(Number::Int(value))
+}
+
+// Number literal which is not hexadecimal, octal or binary
+fn is_decimal_number(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_digit())
+        && !matches!(text.get(..2), Some("0x" | "0X" | "0o" | "0O" | "0b" | "0B"))
 }
 
-fn generate_token_from_string(str: String) -> Token {
+#[allow(clippy::needless_return)]
+fn generate_token_from_string(str: String, position: Position) -> Token {
     if !str.is_empty() {
-        if str.chars().all(|c| char::is_numeric(c) || c == '.') {
+        // Checked by parse_number once the whole number is read
+        if str.starts_with(|c: char| c.is_ascii_digit()) {
             let token = Token {
                 token_type: TokenType::NumberLiteral,
-                value: str.to_string()
+                value: str.to_string(),
+                position: position.clone()
             };
             return token;
-        } else if str.chars().nth(0).unwrap().is_alphabetic() {
+        } else if str.chars().next().unwrap().is_alphabetic() {
             return if Keywords::new().list.contains(&*str) {
                 Token {
                     token_type: TokenType::Keyword,
-                    value: str.to_string()
+                    value: str.to_string(),
+                    position: position.clone()
                 }
             } else {
                 if str == "true" || str == "false" {
                     return Token {
                         token_type: TokenType::BooleanLiteral,
-                        value: str.to_string()
+                        value: str.to_string(),
+                        position: position.clone()
                     }
                 } else {
                     match &str as &str {
                         "and"|"or"|"xor" => {
                             return Token {
                                 token_type: TokenType::Operator,
-                                value: str.to_string()
+                                value: str.to_string(),
+                                position: position.clone()
                             }
                         }
                         _ => {}
@@ -204,13 +530,146 @@ fn generate_token_from_string(str: String) -> Token {
                 }
                 Token {
                     token_type: TokenType::Identifier,
-                    value: str.to_string()
+                    value: str.to_string(),
+                    position: position.clone()
                 }
             }
         }
     }
     return Token {
-        token_type: TokenType::NONE,
-        value: String::new()
+        token_type: TokenType::None,
+        value: String::new(),
+        position: position.clone()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tokenize code and render every token as "Type value", returns every error as "line:column message"
+    fn tokens(code: &str) -> Result<Vec<String>, Vec<String>> {
+        let output = tokenize(code.to_string(), "test.yapko")
+            .map_err(|errors| errors.iter().map(render_error).collect::<Vec<_>>())?;
+        Ok(output.iter().map(|token| format!("{} {}", token.token_type, token.value.escape_debug())).collect())
     }
-}
\ No newline at end of file
+
+    fn render_error(error: &Diagnostic) -> String {
+        format!("{}:{} {}", error.position.line, error.position.column, error.message)
+    }
+
+    #[test]
+    fn integers_in_every_radix() {
+        assert_eq!(parse_number("42"), Ok(Number::Int(42)));
+        assert_eq!(parse_number("0x2A"), Ok(Number::Int(42)));
+        assert_eq!(parse_number("0XfF"), Ok(Number::Int(255)));
+        assert_eq!(parse_number("0o52"), Ok(Number::Int(42)));
+        assert_eq!(parse_number("0b10_1010"), Ok(Number::Int(42)));
+        assert_eq!(parse_number("1_000_000"), Ok(Number::Int(1_000_000)));
+        assert_eq!(parse_number("2147483648"), Ok(Number::Int(MAX_INT_LITERAL)));
+        assert_eq!(parse_number("0x8000_0000"), Ok(Number::Int(MAX_INT_LITERAL)));
+    }
+
+    #[test]
+    fn floats_with_fractions_and_exponents() {
+        assert_eq!(parse_number("1.5"), Ok(Number::Float(1.5)));
+        assert_eq!(parse_number("1e3"), Ok(Number::Float(1000.0)));
+        assert_eq!(parse_number("2.5E-2"), Ok(Number::Float(0.025)));
+        assert_eq!(parse_number("1_000.000_1e+1"), Ok(Number::Float(10000.001)));
+    }
+
+    #[test]
+    fn malformed_numbers_are_errors() {
+        let malformed = ["1_", "1__0", "0x1_", "0x", "0x-1", "0b102", "0o8", "1.2.3", "1.", "1e", "1e+", "1a", "1e2.5"];
+        for literal in malformed {
+            assert_eq!(parse_number(literal), Err(format!("Malformed number {}", literal)), "{}", literal);
+        }
+        for literal in ["2147483649", "99999999999999999999", "0xFFFF_FFFF_FFFF_FFFF_FF"] {
+            let too_large = format!("Integer {} does not fit into Int", literal);
+            assert_eq!(parse_number(literal), Err(too_large), "{}", literal);
+        }
+    }
+
+    #[test]
+    fn numbers_end_at_operators_and_ranges() {
+        assert_eq!(tokens("1e-5-2"), Ok(vec![
+            String::from("NumberLiteral 1e-5"), String::from("Operator -"), String::from("NumberLiteral 2")
+        ]));
+        assert_eq!(tokens("0xe-1"), Ok(vec![
+            String::from("NumberLiteral 0xe"), String::from("Operator -"), String::from("NumberLiteral 1")
+        ]));
+        assert_eq!(tokens("1..2"), Ok(vec![
+            String::from("NumberLiteral 1"), String::from("Operator .."), String::from("NumberLiteral 2")
+        ]));
+        assert_eq!(tokens("1.5.floor"), Ok(vec![
+            String::from("NumberLiteral 1.5"), String::from("Operator ."), String::from("Identifier floor")
+        ]));
+        assert_eq!(tokens("a = 0b12"), Err(vec![String::from("1:5 Malformed number 0b12")]));
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(tokens(r#""a\"b\\c\nd\te\rf\0g""#), Ok(vec![
+            String::from("StringLiteral a\\\"b\\\\c\\nd\\te\\rf\\0g")
+        ]));
+        assert_eq!(tokens(r#""\u{48}\u{e9}\u{1F600}""#), Ok(vec![String::from("StringLiteral Hé😀")]));
+    }
+
+    #[test]
+    fn malformed_escape_sequences_are_errors() {
+        assert_eq!(tokens(r#""a\q""#), Err(vec![String::from("1:3 Unknown escape sequence \\q")]));
+        assert_eq!(tokens(r#""\u48""#), Err(vec![String::from("1:2 Expected '{' after \\u")]));
+        assert_eq!(tokens(r#""\u{48""#), Err(vec![String::from("1:2 Unterminated unicode escape")]));
+        assert_eq!(tokens(r#""\u{}""#), Err(vec![String::from("1:2 Invalid unicode escape \\u{}")]));
+        assert_eq!(tokens(r#""\u{D800}""#), Err(vec![String::from("1:2 Invalid unicode escape \\u{D800}")]));
+        assert_eq!(tokens(r#""\u{1234567}""#), Err(vec![String::from("1:2 Invalid unicode escape \\u{1234567}")]));
+        // Every malformed escape is reported
+        assert_eq!(tokens(r#""\a\b""#), Err(vec![
+            String::from("1:2 Unknown escape sequence \\a"), String::from("1:4 Unknown escape sequence \\b")
+        ]));
+    }
+
+    #[test]
+    fn raw_strings_span_lines_without_escapes() {
+        assert_eq!(tokens("x = \"\"\"one\n\\n \"two\" \"\"\"\n"), Ok(vec![
+            String::from("Identifier x"),
+            String::from("Operator ="),
+            String::from("StringLiteral one\\n\\\\n \\\"two\\\" "),
+            String::from("End \\n")
+        ]));
+    }
+
+    #[test]
+    fn unterminated_strings_are_errors() {
+        assert_eq!(tokens("a = \"one\nb = \"two\""), Err(vec![String::from("1:5 Unterminated string")]));
+        assert_eq!(tokens("a = \"one"), Err(vec![String::from("1:5 Unterminated string")]));
+        assert_eq!(tokens("a = \"\"\"one\n\""), Err(vec![String::from("1:5 Unterminated string")]));
+    }
+
+    #[test]
+    fn comments_separate_tokens_like_whitespace() {
+        assert_eq!(tokens("a// comment\nb"), Ok(vec![
+            String::from("Identifier a"), String::from("End \\n"), String::from("Identifier b")
+        ]));
+        assert_eq!(tokens("a/* comment */b"), Ok(vec![String::from("Identifier a"), String::from("Identifier b")]));
+        assert_eq!(tokens("a / b"), Ok(vec![
+            String::from("Identifier a"), String::from("Operator /"), String::from("Identifier b")
+        ]));
+    }
+
+    #[test]
+    fn block_comments_nest_and_keep_their_lines() {
+        assert_eq!(tokens("a /* outer /* inner */ still comment */ b"), Ok(vec![
+            String::from("Identifier a"), String::from("Identifier b")
+        ]));
+        assert_eq!(tokens("a /* one\ntwo */ b"), Ok(vec![
+            String::from("Identifier a"), String::from("End \\n"), String::from("Identifier b")
+        ]));
+        assert_eq!(tokens("// \"not a string\nb"), Ok(vec![String::from("End \\n"), String::from("Identifier b")]));
+    }
+
+    #[test]
+    fn unterminated_comment_is_an_error() {
+        assert_eq!(tokens("a\n/* one /* two */"), Err(vec![String::from("2:1 Unterminated comment")]));
+        assert_eq!(tokens("a /* b"), Err(vec![String::from("1:3 Unterminated comment")]));
+    }
+}
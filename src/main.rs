@@ -1,84 +1,113 @@
-use std::{env, fs};
-use std::collections::HashMap;
+
+use std::{env, fs, process};
 use std::fs::OpenOptions;
 use std::io::{Write};
+use std::path::Path;
 use crate::bytecode::ByteCode;
+use crate::bytecode::Chunk;
+use crate::diagnostic::Diagnostic;
+use crate::error::YapkoError;
 use crate::interpreter::VM;
-use crate::lexer::{tokenize, TokenType};
+use crate::lexer::tokenize;
 use crate::parser::Parser;
 use crate::yapko::generate_standard;
 
+mod diagnostic;
 mod lexer;
+mod ast;
 mod parser;
 mod bytecode;
+mod disasm;
+mod yapkoc;
 #[path = "VM/interpreter.rs"] mod interpreter;
 #[path = "VM/yapko.rs"] mod yapko;
+#[path = "VM/error.rs"] mod error;
+#[path = "VM/collector.rs"] mod collector;
 
 fn main() {
-    // Get code from file
-    let args: Vec<_> = env::args().collect();
+    let mut args: Vec<_> = env::args().collect();
+    // `yapko run file` is the same as `yapko file`
+    if args.len() > 2 && args[1] == "run" {
+        args.remove(1);
+    }
+    let disassemble = args.len() > 2 && args[1] == "disasm";
+    if disassemble {
+        args.remove(1);
+    }
     if args.len() < 2 {
-        println!("No file given. Use: yapko [filename]");
+        println!("No file given. Use: yapko [run|disasm] [filename] [--compile]");
         return;
     }
-    let code = get_file_content(&args[1]);
-    let mut bytecode = ByteCode::new();
 
-    let compiled_code = compile(code, &mut bytecode);
+    let chunk = match load(&args[1]) {
+        Ok(chunk) => chunk,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
 
-    if args.len() > 2 && args[2] == "--compile" {
-        let filename = args[1][0..args[1].find(".").unwrap_or(0)].to_owned() + ".yapkoc";
-        println!("Code compiled as {}", filename);
+    if disassemble {
+        // Compiled files only know the name of their source, read it if it is still there
+        let source = chunk.lines.first().and_then(|(_, position)| fs::read_to_string(&position.file).ok());
+        print!("{}", disasm::disassemble(&chunk, source.as_deref()));
+    } else if args.len() > 2 && args[2] == "--compile" {
+        let filename = Path::new(&args[1]).with_extension("yapkoc");
+        println!("Code compiled as {}", filename.display());
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(filename)
             .expect("Unable to open file");
-        file.write_all(&*compiled_code).expect("not working idk");
+        file.write_all(&yapkoc::write(&chunk)).expect("Unable to write file");
     } else {
-        let mut commands: HashMap<u8, String> = HashMap::new();
-        for (k, v) in bytecode.commands {
-            commands.insert(v, k);
-        }
-
-        //for c in &compiled_code {
-        //  println!("{} {}", c, *c as char)
-        //}
-
         let mut interpreter = VM::new();
-        interpreter.scopes[0] = generate_standard();
-        interpreter.interpret(compiled_code, commands);
+        interpreter.scopes[0].replace(generate_standard());
+        if let Err(error) = interpreter.interpret(chunk) {
+            eprintln!("{}", render_runtime_error(&error));
+            process::exit(1);
+        }
     }
 }
 
-fn compile(code: String, bytecode: &mut ByteCode) -> Vec<u8> {
-    let tokens = tokenize(code);
-
-    //for token in &tokens {
-    //    println!("{}", token.value);
-    //}
-    let parser = Parser::new();
-    let mut compiled_code: Vec<u8> = vec![];
+// Load compiled file or compile source code, returns rendered errors on failure
+fn load(filename: &String) -> Result<Chunk, String> {
+    let bytes = fs::read(filename).map_err(|error| format!("Cannot open {}: {}", filename, error))?;
+    if yapkoc::is_compiled(&bytes) {
+        return yapkoc::read(&bytes).map_err(|error| format!("Cannot load {}: {}", filename, error));
+    }
 
-    let mut tokens_to_parse = vec![];
-    for token in tokens {
-        if matches!(token.token_type, TokenType::End) {
-            if tokens_to_parse.len() > 0 {
-                let node = parser.parse_tokens(tokens_to_parse.clone());
-                //node.print(0);
+    let code = String::from_utf8(bytes).map_err(|_| format!("{} is not valid UTF-8", filename))?;
+    compile(code.clone(), filename, &mut ByteCode::new()).map_err(
+        |errors| errors.iter().map(|error| error.render(&code)).collect::<Vec<_>>().join("\n")
+    )
+}
 
-                compiled_code.append(&mut bytecode.generate_bytecode(node));
-            }
-            tokens_to_parse.clear();
-        } else {
-            tokens_to_parse.push(token);
-        }
+// Format runtime error with its source line and the calls leading to it
+fn render_runtime_error(error: &YapkoError) -> String {
+    let position = match &error.position {
+        Some(position) => position,
+        None => return error.to_string()
+    };
+    let diagnostic = Diagnostic::new(position.clone(), format!("{}: {}", error.kind, error.message));
+    let mut output = match fs::read_to_string(&position.file) {
+        Ok(source) => diagnostic.render(&source),
+        Err(_) => diagnostic.render("")
+    };
+    for call in &error.trace {
+        output += &format!("\n  called from {}", call);
     }
-
-    return compiled_code;
+    output
 }
 
-fn get_file_content(filename: &String) -> String {
-    return fs::read_to_string(filename)
-        .expect("Cannot open file!");
+fn compile(code: String, file: &str, bytecode: &mut ByteCode) -> Result<Chunk, Vec<Diagnostic>> {
+    let tokens = tokenize(code, file)?;
+    let program = Parser::new().parse_program(tokens)?;
+    bytecode.compile(&program);
+
+    if !bytecode.errors.is_empty() {
+        return Err(std::mem::take(&mut bytecode.errors));
+    }
+    Ok(bytecode.take_chunk())
 }
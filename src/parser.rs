@@ -1,192 +1,844 @@
-use std::collections::{HashMap};
-use crate::lexer::{Token, TokenType};
-
-#[derive(Clone)]
-pub struct Node {
-    pub(crate) token: Token,
-    pub(crate) children: Vec<Node>,
-    pub(crate) invoke: bool,
-}
+use std::collections::HashMap;
+use crate::ast::{BinaryOperator, Block, Else, Expr, Param, Program, Stmt, UnaryOperator};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{MAX_INT_LITERAL, Number, parse_number, Token, TokenType};
 
-impl Node {
-    pub(crate) fn print(&self, amount: i32) {
-        for _i in 0..amount {
-            print!(" ");
-        }
-        println!("{}", self.token.value);
-        for child in &self.children {
-            child.print(amount + 1);
-        }
-    }
+#[derive(Clone, Copy, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
 }
 
+// Prefix '-', '+' and '!' bind tighter than any binary operator except '.'
+const PREFIX_PRECEDENCE: u8 = 10;
+
+// Deepest nesting of statements and expressions, so the parser does not run out of stack
+const MAX_NESTING: usize = 200;
+
+// Syntax error, parsing goes on at the next statement
+pub type ParseError = Diagnostic;
+
 pub struct Parser {
-    operator_values: HashMap<String, i32>,
+    // Precedence (higher binds tighter) and associativity of every binary operator
+    binary_operators: HashMap<String, (u8, Associativity)>,
 }
 
 impl Parser {
     pub fn new() -> Parser {
-        let mut operator: HashMap<String, i32> = Default::default();
-        // Arithmetical operators
-        operator.insert(String::from("="), 0);
-        operator.insert(String::from("+"), 1);
-        operator.insert(String::from("-"), 2);
-        operator.insert(String::from("*"), 3);
-        operator.insert(String::from("/"), 4);
-        operator.insert(String::from("%"), 5);
-        operator.insert(String::from(":"), 6);
+        let mut operator: HashMap<String, (u8, Associativity)> = HashMap::new();
+        operator.insert(String::from("="), (1, Associativity::Right));
 
         // Logical operators
-        operator.insert(String::from("and"), 7);
-        operator.insert(String::from("or"), 8);
-        operator.insert(String::from("xor"), 9);
-        operator.insert(String::from("!"), 10);
+        operator.insert(String::from("or"), (2, Associativity::Left));
+        operator.insert(String::from("xor"), (3, Associativity::Left));
+        operator.insert(String::from("and"), (4, Associativity::Left));
 
         // Comparison operators
-        operator.insert(String::from("<"), 11);
-        operator.insert(String::from("<="), 12);
-        operator.insert(String::from(">"), 13);
-        operator.insert(String::from(">="), 14);
-        operator.insert(String::from("=="), 15);
-        operator.insert(String::from("!="), 16);
+        operator.insert(String::from("=="), (5, Associativity::Left));
+        operator.insert(String::from("!="), (5, Associativity::Left));
+        operator.insert(String::from("<"), (6, Associativity::Left));
+        operator.insert(String::from("<="), (6, Associativity::Left));
+        operator.insert(String::from(">"), (6, Associativity::Left));
+        operator.insert(String::from(">="), (6, Associativity::Left));
+
+        // Range of Ints, e.g. `0..n + 1`
+        operator.insert(String::from(".."), (7, Associativity::Left));
+
+        // Arithmetical operators
+        operator.insert(String::from("+"), (8, Associativity::Left));
+        operator.insert(String::from("-"), (8, Associativity::Left));
+        operator.insert(String::from("*"), (9, Associativity::Left));
+        operator.insert(String::from("/"), (9, Associativity::Left));
+        operator.insert(String::from("%"), (9, Associativity::Left));
+
+        operator.insert(String::from("."), (11, Associativity::Left));
 
-        operator.insert(String::from("."), 17);
+        Parser { binary_operators: operator }
+    }
 
-        Parser { operator_values: operator }
+    // Parse every statement of a file, returns all syntax errors on failure
+    pub fn parse_program(&self, tokens: Vec<Token>) -> Result<Program, Vec<ParseError>> {
+        let mut cursor = Cursor { tokens, index: 0, depth: 0, errors: vec![] };
+        let mut program = vec![];
+
+        loop {
+            cursor.skip_ends();
+            match cursor.peek() {
+                None => break,
+                Some(token) if matches!(token.token_type, TokenType::BracketClose) => {
+                    let error = unexpected(token);
+                    cursor.errors.push(error);
+                    cursor.next();
+                }
+                Some(_) => {
+                    if let Some(statement) = self.parse_statement_or_recover(&mut cursor) {
+                        program.push(statement);
+                    }
+                }
+            }
+        }
+
+        if cursor.errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(cursor.errors)
+        }
+    }
+
+    // Parse a statement, on error record it and skip to the next statement
+    fn parse_statement_or_recover(&self, cursor: &mut Cursor) -> Option<Stmt> {
+        match self.parse_statement(cursor) {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                cursor.errors.push(error);
+                cursor.synchronize();
+                None
+            }
+        }
     }
 
-    pub fn parse_tokens(&self, tokens: Vec<Token>) -> Node {
-        let mut nodes: Vec<Node> = vec![];
+    // Parse one statement, which ends at a newline, a '}' or the end of the file
+    fn parse_statement(&self, cursor: &mut Cursor) -> Result<Stmt, Diagnostic> {
+        let token = cursor.peek().unwrap().clone();
+        if cursor.depth >= MAX_NESTING {
+            return Err(Diagnostic::new(token.position, String::from("Code is nested too deeply")));
+        }
+        cursor.depth += 1;
+        let statement = self.parse_statement_kind(cursor, token);
+        cursor.depth -= 1;
+        let statement = statement?;
 
-        #[derive(Clone)]
-        struct Operator {
-            value: String,
-            index: usize,
+        match cursor.peek() {
+            Some(token) if !matches!(token.token_type, TokenType::End | TokenType::BracketClose) => {
+                Err(unexpected(token))
+            }
+            _ => Ok(statement)
         }
-        let mut operators = vec![];
-        let mut tokens_inside_parens = vec![];
+    }
 
-        let mut parens_opened = 0;
-        let mut index = 0;
-        for token in tokens {
-            if matches!(token.token_type, TokenType::ParenOpen) {
-                parens_opened += 1;
-                if parens_opened == 1 {
-                    continue;
+    fn parse_statement_kind(&self, cursor: &mut Cursor, token: Token) -> Result<Stmt, Diagnostic> {
+        let statement = match token.token_type {
+            // `super` starts an expression
+            TokenType::Keyword if token.value != "super" => {
+                cursor.next();
+                self.parse_keyword(cursor, token)?
+            }
+            TokenType::BracketOpen => Stmt::Block(self.parse_block(cursor, &token)?, token.position),
+            _ => Stmt::Expression(self.parse_expression(cursor, 0)?)
+        };
+        Ok(statement)
+    }
+
+    // Parse the statement started by the keyword
+    fn parse_keyword(&self, cursor: &mut Cursor, keyword: Token) -> Result<Stmt, Diagnostic> {
+        match keyword.value.as_str() {
+            "let" => {
+                let name = self.expect_identifier(cursor, &keyword, "variable name")?;
+                let value = if cursor.peek().is_some_and(|token| is_operator(token, "=")) {
+                    cursor.next();
+                    Some(self.parse_expression(cursor, 0)?)
+                } else {
+                    None
+                };
+                Ok(Stmt::Let {
+                    name: name.value,
+                    value,
+                    position: keyword.position.to(&cursor.previous().position),
+                })
+            }
+            "function" => {
+                let name = self.expect_identifier(cursor, &keyword, "function name")?;
+                let paren = expect(cursor, &name, "'('", |token| matches!(token.token_type, TokenType::ParenOpen))?;
+
+                let mut params = vec![];
+                if !cursor.peek().is_some_and(|token| matches!(token.token_type, TokenType::ParenClose)) {
+                    params.push(self.parse_param(cursor, &paren)?);
                 }
-            } else if matches!(token.token_type, TokenType::ParenClose) {
-                parens_opened -= 1;
-
-                if parens_opened == 0 {
-                    if nodes.len() > 0 && matches!(nodes[index-1].token.token_type, TokenType::Identifier) {
-                        if tokens_inside_parens.len() > 0 {
-                            nodes[index - 1].children.push(
-                                self.parse_tokens(tokens_inside_parens.clone())
-                            );
-                        }
-                        nodes[index - 1].invoke = true;
-                        tokens_inside_parens.clear();
-                    } else {
-                        if tokens_inside_parens.len() > 0 {
-                            nodes.push(self.parse_tokens(
-                                tokens_inside_parens.clone())
-                            );
-                            index += 1;
-                        }
-                        tokens_inside_parens.clear();
-                    }
+                self.expect_paren_close(cursor, &paren)?;
 
-                    continue;
+                let paren_close = cursor.previous().clone();
+                let body = self.parse_block(cursor, &paren_close)?;
+                Ok(Stmt::Function { name: name.value, params, body, position: keyword.position.to(&paren_close.position) })
+            }
+            "return" => {
+                let value = if cursor.at_statement_end() {
+                    None
+                } else {
+                    Some(self.parse_expression(cursor, 0)?)
+                };
+                Ok(Stmt::Return { value, position: keyword.position.to(&cursor.previous().position) })
+            }
+            "if" | "while" => {
+                let condition = self.parse_expression(cursor, 0)?;
+                let position = keyword.position.to(condition.position());
+                let condition_end = cursor.previous().clone();
+                let body = self.parse_block(cursor, &condition_end)?;
+                if keyword.value == "if" {
+                    let otherwise = self.parse_else(cursor)?;
+                    Ok(Stmt::If { condition, body, otherwise, position })
+                } else {
+                    Ok(Stmt::While { condition, body, position })
                 }
             }
+            "for" => {
+                let variable = self.expect_identifier(cursor, &keyword, "loop variable")?;
+                expect(cursor, &variable, "'in'", |token| is_keyword(token, "in"))?;
+                let iterable = self.parse_expression(cursor, 0)?;
+                let position = keyword.position.to(iterable.position());
+                let iterable_end = cursor.previous().clone();
+                let body = self.parse_block(cursor, &iterable_end)?;
+                Ok(Stmt::For { variable: variable.value, iterable, body, position })
+            }
+            "break" => Ok(Stmt::Break(keyword.position)),
+            "continue" => Ok(Stmt::Continue(keyword.position)),
+            "class" => {
+                let name = self.expect_identifier(cursor, &keyword, "class name")?;
+                let superclass = match cursor.peek() {
+                    Some(token) if is_operator(token, ":") => {
+                        let colon = cursor.next().unwrap();
+                        Some(self.expect_identifier(cursor, &colon, "superclass name")?)
+                    }
+                    _ => None
+                };
+                let header_end = superclass.as_ref().unwrap_or(&name).clone();
+                let position = keyword.position.to(&header_end.position);
+                let body = self.parse_block(cursor, &header_end)?;
+                Ok(Stmt::Class { name: name.value, superclass: superclass.map(|token| token.value), body, position })
+            }
+            "execute" => {
+                let file = expect(cursor, &keyword, "file name", |token| {
+                    matches!(token.token_type, TokenType::Identifier | TokenType::StringLiteral)
+                })?;
+                Ok(Stmt::Execute { file: file.value, position: keyword.position.to(&file.position) })
+            }
+            _ => Err(unexpected(&keyword))
+        }
+    }
 
-            if parens_opened == 0 {
-                nodes.push(Node {
-                    token: token.clone(),
-                    children: vec![],
-                    invoke: false,
-                });
+    // Parse `else` or `else if` following the block of an `if`, it may start on the next line
+    fn parse_else(&self, cursor: &mut Cursor) -> Result<Option<Else>, Diagnostic> {
+        if !cursor.peek_past_ends().is_some_and(|token| is_keyword(token, "else")) {
+            return Ok(None);
+        }
+        cursor.skip_ends();
+        let keyword = cursor.next().unwrap();
+
+        match cursor.peek() {
+            Some(token) if is_keyword(token, "if") => {
+                let keyword = cursor.next().unwrap();
+                Ok(Some(Else::If(Box::new(self.parse_keyword(cursor, keyword)?))))
+            }
+            _ => Ok(Some(Else::Block(self.parse_block(cursor, &keyword)?)))
+        }
+    }
 
-                match nodes.last().expect("Node loading error").token.token_type {
-                    TokenType::Operator => {
-                        operators.push(Operator {
-                            value: nodes[nodes.len() - 1].clone().token.value,
-                            index,
-                        });
+    // Argument of a function declaration, e.g. `n: Int`
+    fn parse_param(&self, cursor: &mut Cursor, paren: &Token) -> Result<Param, Diagnostic> {
+        let name = self.expect_identifier(cursor, paren, "argument name")?;
+        let colon = expect(cursor, &name, "':' and the type of the argument", |token| is_operator(token, ":"))?;
+        let type_name = self.expect_identifier(cursor, &colon, "argument type")?;
+        Ok(Param { name: name.value, type_name: type_name.value })
+    }
+
+    // Parse '{', the statements of the block and '}', the '{' may start on the next line
+    fn parse_block(&self, cursor: &mut Cursor, after: &Token) -> Result<Block, Diagnostic> {
+        cursor.skip_ends();
+        let bracket = expect(cursor, after, "'{'", |token| matches!(token.token_type, TokenType::BracketOpen))?;
+
+        let mut body = vec![];
+        loop {
+            cursor.skip_ends();
+            match cursor.peek() {
+                None => return Err(Diagnostic::new(bracket.position, String::from("Unclosed '{'"))),
+                Some(token) if matches!(token.token_type, TokenType::BracketClose) => {
+                    let end = cursor.next().unwrap().position;
+                    return Ok(Block { body, end });
+                }
+                Some(_) => {
+                    if let Some(statement) = self.parse_statement_or_recover(cursor) {
+                        body.push(statement);
                     }
-                    _ => {}
                 }
-
-                index += 1;
-            } else {
-                tokens_inside_parens.push(token);
             }
         }
+    }
 
-        operators.sort_by(
-            |a, b| self.operator_values[&b.value].cmp(
-                &self.operator_values[&a.value]
-            )
-        );
-
-        for operator_index in 0..operators.len() {
-            let operator = &operators[operator_index];
-            let index = operator.index.clone();
-            if operator.value == "!" {
-                // add right side
-                let node = nodes[operator.index + 1].clone();
-                nodes[operator.index].children.push(node);
+    // Parse operators binding at least as tight as min_precedence
+    fn parse_expression(&self, cursor: &mut Cursor, min_precedence: u8) -> Result<Expr, Diagnostic> {
+        if cursor.depth >= MAX_NESTING {
+            let position = cursor.peek().unwrap_or(cursor.previous()).position.clone();
+            return Err(Diagnostic::new(position, String::from("Code is nested too deeply")));
+        }
+        cursor.depth += 1;
+        let expression = self.parse_binary(cursor, min_precedence);
+        cursor.depth -= 1;
+        expression
+    }
 
-                nodes.remove(operator.index + 1);
+    fn parse_binary(&self, cursor: &mut Cursor, min_precedence: u8) -> Result<Expr, Diagnostic> {
+        let mut left = self.parse_prefix(cursor)?;
 
-                // one item was removed, so every index higher than the current one
-                // needs to be lowered by 1
-                for operator2 in &mut operators {
-                    if operator2.index > index {
-                        operator2.index -= 1;
+        loop {
+            let (precedence, associativity) = match cursor.peek() {
+                Some(token) if matches!(token.token_type, TokenType::Operator) => {
+                    match self.binary_operators.get(&token.value) {
+                        Some(operator) => *operator,
+                        None => return Err(unexpected(token))
                     }
                 }
+                _ => break
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            let operator = cursor.next().unwrap();
+
+            if operator.value == "." {
+                // Member name, possibly called
+                let name = match cursor.next() {
+                    Some(token) if matches!(token.token_type, TokenType::Identifier) => token,
+                    Some(token) => return Err(Diagnostic::new(
+                        token.position.clone(),
+                        format!("Expected member name after '.', found '{}'", token.value)
+                    )),
+                    None => return Err(expected_after(cursor, &operator, "member name"))
+                };
+                let member = Expr::Member {
+                    position: left.position().to(&name.position),
+                    object: Box::new(left),
+                    name: name.value,
+                };
+                left = self.parse_call(cursor, member)?;
+                continue;
+            }
+
+            let right = if associativity == Associativity::Left {
+                self.parse_expression(cursor, precedence + 1)?
             } else {
-                // add left side
-                let node = nodes[operator.index - 1].clone();
-                nodes[operator.index].children.push(node);
-
-                // add right side
-                let node = nodes[operator.index + 1].clone();
-                nodes[operator.index].children.push(node);
-
-                // remove used nodes
-                nodes.remove(operator.index + 1);
-                nodes.remove(operator.index - 1);
-
-                // two items were removed, so every index higher than the current one
-                // needs to be lowered by 2
-                for operator2 in &mut operators {
-                    if operator2.index > index {
-                        operator2.index -= 2;
-                    }
+                self.parse_expression(cursor, precedence)?
+            };
+            let position = left.position().to(right.position());
+
+            left = if operator.value == "=" {
+                if !matches!(left, Expr::Variable(..) | Expr::Member { .. }) {
+                    return Err(Diagnostic::new(left.position().clone(), String::from("Cannot assign to this expression")));
                 }
+                Expr::Assign { target: Box::new(left), value: Box::new(right), position }
+            } else {
+                Expr::Binary {
+                    operator: binary_operator(&operator.value),
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    position,
+                }
+            };
+        }
+        Ok(left)
+    }
+
+    // Parse a value, a call, an expression in parentheses or a prefix operator with its operand
+    fn parse_prefix(&self, cursor: &mut Cursor) -> Result<Expr, Diagnostic> {
+        let token = match cursor.peek() {
+            Some(token) if !matches!(token.token_type, TokenType::End) => cursor.next().unwrap(),
+            _ => return Err(expected_after(cursor, cursor.previous(), "expression"))
+        };
+
+        match token.token_type {
+            TokenType::NumberLiteral => number_literal(token),
+            TokenType::StringLiteral => Ok(Expr::String(token.value, token.position)),
+            TokenType::BooleanLiteral => Ok(Expr::Boolean(token.value == "true", token.position)),
+            TokenType::Identifier => self.parse_call(cursor, Expr::Variable(token.value, token.position)),
+            TokenType::Keyword if token.value == "super" => {
+                let dot = expect(cursor, &token, "'.'", |token| is_operator(token, "."))?;
+                let name = self.expect_identifier(cursor, &dot, "member name")?;
+                let position = token.position.to(&name.position);
+                self.parse_call(cursor, Expr::Super { name: name.value, position })
+            }
+            TokenType::ParenOpen => {
+                let expression = self.parse_expression(cursor, 0)?;
+                self.expect_paren_close(cursor, &token)?;
+                Ok(expression)
+            }
+            // Digits of the smallest Int do not fit into Int on their own
+            TokenType::Operator if token.value == "-" && is_smallest_int(cursor) => {
+                let position = token.position.to(&cursor.next().unwrap().position);
+                Ok(Expr::Int(i32::MIN, position))
             }
+            TokenType::Operator if matches!(token.value.as_str(), "-" | "+" | "!") => {
+                let operand = self.parse_expression(cursor, PREFIX_PRECEDENCE)?;
+                let operator = match token.value.as_str() {
+                    "-" => UnaryOperator::Neg,
+                    "+" => UnaryOperator::Plus,
+                    _ => UnaryOperator::Not
+                };
+                Ok(Expr::Unary {
+                    operator,
+                    position: token.position.to(operand.position()),
+                    operand: Box::new(operand),
+                })
+            }
+            _ => Err(unexpected(&token))
         }
+    }
 
-        let mut max_index = if nodes.len() >= 1 {
-            nodes.len() - 1
-        } else {
-            0
+    // Parse the arguments if the callee is followed by '('
+    fn parse_call(&self, cursor: &mut Cursor, callee: Expr) -> Result<Expr, Diagnostic> {
+        if !cursor.peek().is_some_and(|token| matches!(token.token_type, TokenType::ParenOpen)) {
+            return Ok(callee);
+        }
+        let paren = cursor.next().unwrap();
+
+        let mut arguments = vec![];
+        if !cursor.peek().is_some_and(|token| matches!(token.token_type, TokenType::ParenClose)) {
+            arguments.push(self.parse_expression(cursor, 0)?);
+        }
+        self.expect_paren_close(cursor, &paren)?;
+
+        Ok(Expr::Call {
+            position: callee.position().to(&cursor.previous().position),
+            callee: Box::new(callee),
+            arguments,
+        })
+    }
+
+    fn expect_paren_close(&self, cursor: &mut Cursor, paren: &Token) -> Result<(), Diagnostic> {
+        match cursor.peek() {
+            Some(token) if matches!(token.token_type, TokenType::ParenClose) => {
+                cursor.next();
+                Ok(())
+            }
+            Some(token) if !matches!(token.token_type, TokenType::End) => Err(unexpected(token)),
+            _ => Err(Diagnostic::new(paren.position.clone(), String::from("Unclosed '('")))
+        }
+    }
+
+    fn expect_identifier(&self, cursor: &mut Cursor, after: &Token, what: &str) -> Result<Token, Diagnostic> {
+        expect(cursor, after, what, |token| matches!(token.token_type, TokenType::Identifier))
+    }
+}
+
+// Tokens of the file being parsed
+struct Cursor {
+    tokens: Vec<Token>,
+    index: usize,
+    // Statements and expressions currently being parsed inside each other
+    depth: usize,
+    errors: Vec<ParseError>,
+}
+
+impl Cursor {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        if token.is_some() {
+            self.index += 1;
+        }
+        token
+    }
+
+    // Last consumed token
+    fn previous(&self) -> &Token {
+        &self.tokens[self.index.saturating_sub(1)]
+    }
+
+    // Next token which is not the end of a line
+    fn peek_past_ends(&self) -> Option<&Token> {
+        self.tokens[self.index..].iter().find(|token| !matches!(token.token_type, TokenType::End))
+    }
+
+    fn skip_ends(&mut self) {
+        while self.peek().is_some_and(|token| matches!(token.token_type, TokenType::End)) {
+            self.index += 1;
+        }
+    }
+
+    // Skip the rest of a malformed statement, including blocks it opened, up to the next line or the '}' closing
+    // the block it is in
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.token_type {
+                TokenType::End if depth == 0 => return,
+                TokenType::BracketClose if depth == 0 => return,
+                TokenType::BracketOpen => depth += 1,
+                TokenType::BracketClose => depth -= 1,
+                _ => {}
+            }
+            self.index += 1;
+        }
+    }
+
+    fn at_statement_end(&self) -> bool {
+        match self.peek() {
+            Some(token) => matches!(token.token_type, TokenType::End | TokenType::BracketClose),
+            None => true
+        }
+    }
+}
+
+// Take the next token if it is the expected one
+fn expect(cursor: &mut Cursor, after: &Token, what: &str, is_expected: fn(&Token) -> bool) -> Result<Token, Diagnostic> {
+    match cursor.peek() {
+        Some(token) if is_expected(token) => Ok(cursor.next().unwrap()),
+        _ => Err(expected_after(cursor, after, what))
+    }
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token.token_type, TokenType::Keyword) && token.value == keyword
+}
+
+fn is_operator(token: &Token, operator: &str) -> bool {
+    matches!(token.token_type, TokenType::Operator) && token.value == operator
+}
+
+fn number_literal(token: Token) -> Result<Expr, Diagnostic> {
+    match parse_number(&token.value) {
+        Ok(Number::Int(value)) => match i32::try_from(value) {
+            Ok(value) => Ok(Expr::Int(value, token.position)),
+            Err(_) => Err(Diagnostic::new(token.position, format!("Integer {} does not fit into Int", token.value)))
+        },
+        Ok(Number::Float(value)) => Ok(Expr::Float(value, token.position)),
+        Err(message) => Err(Diagnostic::new(token.position, message))
+    }
+}
+
+// Next token is the literal 2147483648, which is not a member access (`-2147483648.toString()` negates the result)
+fn is_smallest_int(cursor: &Cursor) -> bool {
+    let literal = match cursor.peek() {
+        Some(token) if matches!(token.token_type, TokenType::NumberLiteral) => token,
+        _ => return false
+    };
+    let member_access = cursor.tokens.get(cursor.index + 1).is_some_and(|token| is_operator(token, "."));
+    parse_number(&literal.value) == Ok(Number::Int(MAX_INT_LITERAL)) && !member_access
+}
+
+// Operators in the precedence table other than '=' and '.'
+fn binary_operator(operator: &str) -> BinaryOperator {
+    match operator {
+        "+" => BinaryOperator::Add,
+        "-" => BinaryOperator::Sub,
+        "*" => BinaryOperator::Mul,
+        "/" => BinaryOperator::Div,
+        "%" => BinaryOperator::Mod,
+        "<" => BinaryOperator::Less,
+        "<=" => BinaryOperator::LessEqual,
+        ">" => BinaryOperator::Greater,
+        ">=" => BinaryOperator::GreaterEqual,
+        "==" => BinaryOperator::Equal,
+        "!=" => BinaryOperator::NotEqual,
+        "and" => BinaryOperator::And,
+        "or" => BinaryOperator::Or,
+        ".." => BinaryOperator::Range,
+        _ => BinaryOperator::Xor
+    }
+}
+
+fn unexpected(token: &Token) -> Diagnostic {
+    Diagnostic::new(token.position.clone(), format!("Unexpected '{}'", token.value))
+}
+
+// Error for something missing, pointing at the token it should follow if the line ended
+fn expected_after(cursor: &Cursor, after: &Token, what: &str) -> Diagnostic {
+    match cursor.peek() {
+        Some(token) if !matches!(token.token_type, TokenType::End) => {
+            Diagnostic::new(token.position.clone(), format!("Expected {}, found '{}'", what, token.value))
+        }
+        _ => Diagnostic::new(after.position.clone(), format!("Expected {} after '{}'", what, after.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    // Parse code and render its statements as S-expressions separated by "; "
+    fn parse(code: &str) -> Result<String, String> {
+        let program = parse_all(code).map_err(|errors| errors[0].split_once(' ').unwrap().1.to_string())?;
+        Ok(program.iter().map(render_statement).collect::<Vec<_>>().join("; "))
+    }
+
+    // Parse code, returns every error as "line:column message"
+    fn parse_all(code: &str) -> Result<Program, Vec<String>> {
+        let render_errors = |errors: Vec<Diagnostic>| -> Vec<String> {
+            errors.iter().map(|error| format!("{}:{} {}", error.position.line, error.position.column, error.message)).collect()
         };
-        for index in 0..nodes.len() {
-            if index > max_index {
-                break;
+        let tokens = tokenize(code.to_string(), "test.yapko").map_err(render_errors)?;
+        Parser::new().parse_program(tokens).map_err(render_errors)
+    }
+
+    fn render_statement(statement: &Stmt) -> String {
+        match statement {
+            Stmt::Expression(expression) => render(expression),
+            Stmt::Let { name, value: Some(value), .. } => format!("(let {} {})", name, render(value)),
+            Stmt::Let { name, value: None, .. } => format!("(let {})", name),
+            Stmt::Function { name, params, body, .. } => {
+                let params: Vec<_> = params.iter().map(|param| format!("{}: {}", param.name, param.type_name)).collect();
+                format!("(function {} ({}) {})", name, params.join(" "), render_block(body))
+            }
+            Stmt::Return { value: Some(value), .. } => format!("(return {})", render(value)),
+            Stmt::Return { value: None, .. } => String::from("return"),
+            Stmt::If { condition, body, otherwise: None, .. } => format!("(if {} {})", render(condition), render_block(body)),
+            Stmt::If { condition, body, otherwise: Some(Else::Block(otherwise)), .. } => {
+                format!("(if {} {} else {})", render(condition), render_block(body), render_block(otherwise))
+            }
+            Stmt::If { condition, body, otherwise: Some(Else::If(otherwise)), .. } => {
+                format!("(if {} {} else {})", render(condition), render_block(body), render_statement(otherwise))
             }
-            if matches!(nodes[max_index - index].token.token_type, TokenType::Keyword) {
-                if (max_index - index) + 1 <= nodes.len() {
-                    let node = nodes[(max_index - index) + 1].clone();
-                    nodes[max_index - index].children.push(node);
-                    nodes.remove((max_index - index) + 1);
-                    max_index -= 1;
+            Stmt::While { condition, body, .. } => format!("(while {} {})", render(condition), render_block(body)),
+            Stmt::Class { name, superclass: None, body, .. } => format!("(class {} {})", name, render_block(body)),
+            Stmt::Class { name, superclass: Some(superclass), body, .. } => {
+                format!("(class {} : {} {})", name, superclass, render_block(body))
+            }
+            Stmt::Execute { file, .. } => format!("(execute {})", file),
+            Stmt::For { variable, iterable, body, .. } => {
+                format!("(for {} {} {})", variable, render(iterable), render_block(body))
+            }
+            Stmt::Break(_) => String::from("break"),
+            Stmt::Continue(_) => String::from("continue"),
+            Stmt::Block(body, _) => render_block(body),
+        }
+    }
+
+    fn render_block(block: &Block) -> String {
+        format!("{{{}}}", block.body.iter().map(render_statement).collect::<Vec<_>>().join("; "))
+    }
+
+    fn render(expression: &Expr) -> String {
+        match expression {
+            Expr::Int(value, _) => value.to_string(),
+            Expr::Float(value, _) => format!("{:?}", value),
+            Expr::String(value, _) => format!("{:?}", value),
+            Expr::Boolean(value, _) => value.to_string(),
+            Expr::Variable(name, _) => name.clone(),
+            Expr::Binary { operator, left, right, .. } => format!("({} {} {})", operator, render(left), render(right)),
+            Expr::Unary { operator, operand, .. } => format!("({} {})", operator, render(operand)),
+            Expr::Assign { target, value, .. } => format!("(= {} {})", render(target), render(value)),
+            Expr::Call { callee, arguments, .. } => {
+                let mut output = format!("(call {}", render(callee));
+                for argument in arguments {
+                    output += &format!(" {}", render(argument));
                 }
+                output + ")"
             }
+            Expr::Member { object, name, .. } => format!("(. {} {})", render(object), name),
+            Expr::Super { name, .. } => format!("(super {})", name),
+        }
+    }
+
+    fn assert_parses(code: &str, expected: &str) {
+        assert_eq!(parse(code), Ok(String::from(expected)), "parsing {}", code);
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        assert_parses("a = b = 1", "(= a (= b 1))");
+        assert_parses("a = 1 + 2", "(= a (+ 1 2))");
+        assert_parses("p.x = 2", "(= (. p x) 2)");
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_parses("a or b xor c", "(or a (xor b c))");
+        assert_parses("a xor b and c", "(xor a (and b c))");
+        assert_parses("a and b or c", "(or (and a b) c)");
+        assert_parses("a and b and c", "(and (and a b) c)");
+        assert_parses("a == b and c != d", "(and (== a b) (!= c d))");
+    }
+
+    #[test]
+    fn equality_binds_looser_than_comparison() {
+        assert_parses("a == b < c", "(== a (< b c))");
+        assert_parses("a <= b != c >= d", "(!= (<= a b) (>= c d))");
+        assert_parses("a == b == c", "(== (== a b) c)");
+    }
+
+    #[test]
+    fn comparison_binds_looser_than_arithmetic() {
+        assert_parses("i * i > limit", "(> (* i i) limit)");
+        assert_parses("a + 1 < b - 1", "(< (+ a 1) (- b 1))");
+    }
+
+    #[test]
+    fn additive_is_left_associative() {
+        assert_parses("a - b - c", "(- (- a b) c)");
+        assert_parses("a - b + c", "(+ (- a b) c)");
+    }
+
+    #[test]
+    fn multiplicative_is_left_associative_and_binds_tighter() {
+        assert_parses("a / b * c", "(* (/ a b) c)");
+        assert_parses("a % b / c", "(/ (% a b) c)");
+        assert_parses("a + b * c", "(+ a (* b c))");
+        assert_parses("a * b - c / d", "(- (* a b) (/ c d))");
+    }
+
+    #[test]
+    fn prefix_operators() {
+        assert_parses("-a * b", "(* (- a) b)");
+        assert_parses("a * -b", "(* a (- b))");
+        assert_parses("- -a", "(- (- a))");
+        assert_parses("+a - b", "(- (+ a) b)");
+        assert_parses("!a == b", "(== (! a) b)");
+        assert_parses("!(a == b)", "(! (== a b))");
+    }
+
+    #[test]
+    fn member_access_binds_tightest() {
+        assert_parses("-a.b", "(- (. a b))");
+        assert_parses("a.b.c", "(. (. a b) c)");
+        assert_parses("p.show()", "(call (. p show))");
+        assert_parses("a.add(b) * 2", "(* (call (. a add) b) 2)");
+    }
+
+    #[test]
+    fn calls_and_parentheses() {
+        assert_parses("f()", "(call f)");
+        assert_parses("f(a + 1)", "(call f (+ a 1))");
+        assert_parses("f(g(x))", "(call f (call g x))");
+        assert_parses("(a + b) * c", "(* (+ a b) c)");
+        assert_parses("a - (b - c)", "(- a (- b c))");
+    }
+
+    #[test]
+    fn literals() {
+        assert_parses("0x10 + 1.5", "(+ 16 1.5)");
+        assert_parses("\"hi\" == true", "(== \"hi\" true)");
+    }
+
+    #[test]
+    fn smallest_int_literal() {
+        assert_parses("-2147483648", "-2147483648");
+        assert_parses("a * -0x8000_0000", "(* a -2147483648)");
+        assert_parses("-2147483647", "(- 2147483647)");
+        let too_large = |literal: &str| Err(format!("Integer {} does not fit into Int", literal));
+        assert_eq!(parse("2147483648"), too_large("2147483648"));
+        assert_eq!(parse("a - 2147483648"), too_large("2147483648"));
+        assert_eq!(parse("-2147483649"), too_large("2147483649"));
+        assert_eq!(parse("-2147483648.toString()"), too_large("2147483648"));
+    }
+
+    #[test]
+    fn keyword_statements() {
+        assert_parses("let x = 1", "(let x 1)");
+        assert_parses("let x", "(let x)");
+        assert_parses("return", "return");
+        assert_parses("return n * fact(n - 1)", "(return (* n (call fact (- n 1))))");
+        assert_parses("execute other", "(execute other)");
+        assert_parses("while a {\n if b { break } else { continue }\n}", "(while a {(if b {break} else {continue})})");
+    }
+
+    #[test]
+    fn blocks_belong_to_their_statement() {
+        assert_parses("function add(a: Int) {\n return a + 1\n}", "(function add (a: Int) {(return (+ a 1))})");
+        assert_parses("function f() {}", "(function f () {})");
+        assert_parses("while i < 3 { i = i + 1 }", "(while (< i 3) {(= i (+ i 1))})");
+        assert_parses("if a\n{\n b()\n}\nc", "(if a {(call b)}); c");
+        assert_parses("class A {\n let x = 1\n function get() {\n  return x\n }\n}",
+                      "(class A {(let x 1); (function get () {(return x)})})");
+        assert_parses("{\n let a = 1\n {\n }\n}", "{(let a 1); {}}");
+    }
+
+    #[test]
+    fn inheritance() {
+        assert_parses("class Dog : Animal {\n function speak() {\n  return super.speak() + 1\n }\n}",
+                      "(class Dog : Animal {(function speak () {(return (+ (call (super speak)) 1))})})");
+        assert_parses("super.a.b = 1", "(= (. (super a) b) 1)");
+        assert_parses("super.init(n)", "(call (super init) n)");
+        assert_eq!(parse("class Dog : {}"), Err(String::from("Expected superclass name after ':'")));
+        assert_eq!(parse("super()"), Err(String::from("Expected '.', found '('")));
+    }
+
+    #[test]
+    fn ranges_and_for_loops() {
+        assert_parses("0..10", "(.. 0 10)");
+        assert_parses("a + 1..b * 2", "(.. (+ a 1) (* b 2))");
+        assert_parses("0..n < m", "(< (.. 0 n) m)");
+        assert_parses("1.5..2", "(.. 1.5 2)");
+        assert_parses("for i in 0..3 { f(i) }", "(for i (.. 0 3) {(call f i)})");
+        assert_parses("for c in \"abc\" {\n continue\n}", "(for c \"abc\" {continue})");
+        assert_eq!(parse("for i 0..3 {}"), Err(String::from("Expected 'in', found '0'")));
+        assert_eq!(parse("for in x {}"), Err(String::from("Expected loop variable, found 'in'")));
+        assert_eq!(parse("0...1"), Err(String::from("Unexpected '.'")));
+    }
+
+    #[test]
+    fn else_branches() {
+        assert_parses("if a { b } else { c }", "(if a {b} else {c})");
+        assert_parses("if a {\n b\n}\nelse\n{\n c\n}", "(if a {b} else {c})");
+        assert_parses("if a { b } else if c { d } else { e }", "(if a {b} else (if c {d} else {e}))");
+        assert_parses("if a { b }\n\nc", "(if a {b}); c");
+    }
+
+    #[test]
+    fn malformed_statements_are_errors() {
+        assert_eq!(parse("a +"), Err(String::from("Expected expression after '+'")));
+        assert_eq!(parse("(a"), Err(String::from("Unclosed '('")));
+        assert_eq!(parse("a b"), Err(String::from("Unexpected 'b'")));
+        assert_eq!(parse("a.1"), Err(String::from("Expected member name after '.', found '1'")));
+        assert_eq!(parse("* a"), Err(String::from("Unexpected '*'")));
+        assert_eq!(parse("f(a))"), Err(String::from("Unexpected ')'")));
+        assert_eq!(parse("1 = a"), Err(String::from("Cannot assign to this expression")));
+    }
+
+    #[test]
+    fn malformed_blocks_are_errors() {
+        assert_eq!(parse("while a {\n b\n"), Err(String::from("Unclosed '{'")));
+        assert_eq!(parse("a\n}"), Err(String::from("Unexpected '}'")));
+        assert_eq!(parse("if a\nb"), Err(String::from("Expected '{', found 'b'")));
+        assert_eq!(parse("if a"), Err(String::from("Expected '{' after 'a'")));
+        assert_eq!(parse("function f(a) {}"), Err(String::from("Expected ':' and the type of the argument, found ')'")));
+        assert_eq!(parse("let 1"), Err(String::from("Expected variable name, found '1'")));
+        assert_eq!(parse("if a { b } c"), Err(String::from("Unexpected 'c'")));
+        assert_eq!(parse("else { b }"), Err(String::from("Unexpected 'else'")));
+        assert_eq!(parse("while a { break b }"), Err(String::from("Unexpected 'b'")));
+        assert_eq!(parse("if a { b } else c"), Err(String::from("Expected '{', found 'c'")));
+    }
+
+    #[test]
+    fn errors_of_every_statement_are_reported() {
+        let code = "let x = 1\n}\nfunction f(a) {\n printLine(a +)\n}\nlet = 2\nwhile x < 3 {\n x = x + 1\n";
+        assert_eq!(parse_all(code).unwrap_err(), vec![
+            String::from("2:1 Unexpected '}'"),
+            String::from("3:13 Expected ':' and the type of the argument, found ')'"),
+            String::from("4:15 Unexpected ')'"),
+            String::from("6:5 Expected variable name, found '='"),
+            String::from("7:13 Unclosed '{'"),
+        ]);
+    }
+
+    #[test]
+    fn parsing_resumes_inside_the_block() {
+        let code = "while a {\n b b\n c\n}\nd )\ne";
+        assert_eq!(parse_all(code).unwrap_err(), vec![String::from("2:4 Unexpected 'b'"), String::from("5:3 Unexpected ')'")]);
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let parentheses = format!("{}a{}", "(".repeat(10000), ")".repeat(10000));
+        assert_eq!(parse(&parentheses), Err(String::from("Code is nested too deeply")));
+        let negations = format!("{}a", "-".repeat(10000));
+        assert_eq!(parse(&negations), Err(String::from("Code is nested too deeply")));
+        let blocks = format!("{}{}", "{\n".repeat(10000), "}\n".repeat(10000));
+        assert_eq!(parse(&blocks), Err(String::from("Code is nested too deeply")));
+    }
+
+    #[test]
+    fn malformed_input_does_not_panic() {
+        let code = "class A {\n let x = -(1 + 2.5) * f(\"s\")\n function g(n: Int) {\n  if n >= 0 and !b {\n   return p.q.r(n % 2)\n  }\n  while true { execute other }\n }\n}\n";
+        let tokens = tokenize(code.to_string(), "test.yapko").unwrap();
+        // Every prefix and every sequence with one token missing
+        for end in 0..=tokens.len() {
+            let _ = Parser::new().parse_program(tokens[..end].to_vec());
+        }
+        for missing in 0..tokens.len() {
+            let mut broken = tokens.clone();
+            broken.remove(missing);
+            let _ = Parser::new().parse_program(broken);
         }
-        nodes[0].clone()
     }
 }
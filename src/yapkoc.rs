@@ -0,0 +1,352 @@
+// Compiled file (.yapkoc) format
+//
+// Every number is stored as little-endian, strings are stored as their length (u32)
+// followed by UTF-8 bytes.
+//
+//   magic               4 bytes, "YPKC"
+//   format version      u16, layout of this file, see FORMAT_VERSION
+//   opcode-set version  u16, numbering of instructions, see OPCODE_SET_VERSION in bytecode.rs
+//   constant pool       u32 count, then for every constant: u8 tag (0 for a string) and its data
+//   code                u32 length, then the bytecode
+//   debug info          string with the source file name, u32 entry count, then for every
+//                       entry u32 instruction offset, line, column, span start and span end
+//   checksum            u32, CRC-32 of every byte before it
+//
+// A file is only loaded if both versions match the ones of the running interpreter.
+
+use std::fmt;
+use crate::bytecode::{Chunk, CONSTANT_SIZE, OPCODE_SET_VERSION, OpCode};
+use crate::diagnostic::Position;
+
+pub const MAGIC: [u8; 4] = *b"YPKC";
+// Bump whenever the layout described above changes
+pub const FORMAT_VERSION: u16 = 2;
+
+const STRING_CONSTANT: u8 = 0;
+
+#[derive(Debug)]
+pub enum LoadError {
+    // File does not start with MAGIC
+    NotCompiled,
+    UnsupportedFormat(u16),
+    IncompatibleOpcodes(u16),
+    ChecksumMismatch,
+    // Byte at the offset of the code is not an instruction
+    UnknownOpcode(usize, u8),
+    // File ends in the middle of a section
+    Truncated,
+    Malformed(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NotCompiled => write!(f, "not a compiled Yapko file"),
+            LoadError::UnsupportedFormat(version) => write!(
+                f, "file format version {} is not supported (expected {}), recompile the source",
+                version, FORMAT_VERSION
+            ),
+            LoadError::IncompatibleOpcodes(version) => write!(
+                f, "compiled for opcode set {} but this interpreter uses {}, recompile the source",
+                version, OPCODE_SET_VERSION
+            ),
+            LoadError::ChecksumMismatch => write!(f, "checksum mismatch, file is corrupted"),
+            LoadError::UnknownOpcode(offset, byte) => write!(f, "unknown opcode {} at offset {}", byte, offset),
+            LoadError::Truncated => write!(f, "unexpected end of file"),
+            LoadError::Malformed(message) => write!(f, "malformed file: {}", message),
+        }
+    }
+}
+
+// CRC-32 (IEEE 802.3)
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+pub fn write(chunk: &Chunk) -> Vec<u8> {
+    fn push_u32(output: &mut Vec<u8>, value: usize) {
+        output.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    let mut output = MAGIC.to_vec();
+    output.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    output.extend_from_slice(&OPCODE_SET_VERSION.to_le_bytes());
+
+    push_u32(&mut output, chunk.constants.len());
+    for constant in &chunk.constants {
+        output.push(STRING_CONSTANT);
+        push_u32(&mut output, constant.len());
+        output.extend_from_slice(constant.as_bytes());
+    }
+
+    push_u32(&mut output, chunk.code.len());
+    output.extend_from_slice(&chunk.code);
+
+    // Chunk is compiled from a single file
+    let file = chunk.lines.first().map_or("", |(_, position)| position.file.as_str());
+    push_u32(&mut output, file.len());
+    output.extend_from_slice(file.as_bytes());
+    push_u32(&mut output, chunk.lines.len());
+    for (offset, position) in &chunk.lines {
+        push_u32(&mut output, *offset);
+        push_u32(&mut output, position.line);
+        push_u32(&mut output, position.column);
+        push_u32(&mut output, position.start);
+        push_u32(&mut output, position.end);
+    }
+
+    let checksum = checksum(&output);
+    output.extend_from_slice(&checksum.to_le_bytes());
+    output
+}
+
+// Reads sections of the file one after another
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], LoadError> {
+        if self.bytes.len() - self.position < length {
+            return Err(LoadError::Truncated);
+        }
+        let slice = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.u32()?;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| LoadError::Malformed(String::from("string is not valid UTF-8")))
+    }
+}
+
+// Check that the code is made of known instructions with complete operands, and that every jump lands on an
+// instruction or at the end of the code
+fn validate_code(code: &[u8], constant_count: usize) -> Result<(), LoadError> {
+    fn read_u32(code: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes([code[offset], code[offset + 1], code[offset + 2], code[offset + 3]]) as usize
+    }
+
+    let mut starts = vec![false; code.len() + 1];
+    starts[code.len()] = true;
+    // Offset of every jump and where it leads to, None if it leads before the start of the code
+    let mut jumps = vec![];
+    let mut i = 0;
+    while i < code.len() {
+        let start = i;
+        starts[start] = true;
+        let opcode = OpCode::try_from(code[i]).map_err(|byte| LoadError::UnknownOpcode(i, byte))?;
+        i += 1;
+        if code.len() - i < opcode.operand_size() {
+            return Err(LoadError::Malformed(format!("incomplete operand of {} at offset {}", opcode, start)));
+        }
+        if opcode.has_constant() {
+            let index = read_u32(code, i);
+            if index >= constant_count {
+                return Err(LoadError::Malformed(format!("constant {} at offset {} does not exist", index, start)));
+            }
+        }
+        let end = i + opcode.operand_size();
+        // Offsets are relative to the end of the operand
+        match opcode {
+            OpCode::Jump | OpCode::JumpIfFalse => jumps.push((start, end.checked_add(read_u32(code, i)))),
+            OpCode::FunStart => jumps.push((start, end.checked_add(read_u32(code, i + CONSTANT_SIZE + 1)))),
+            OpCode::ForNext => jumps.push((start, end.checked_add(read_u32(code, i + CONSTANT_SIZE)))),
+            OpCode::Loop => jumps.push((start, end.checked_sub(read_u32(code, i)))),
+            _ => {}
+        }
+        i = end;
+    }
+
+    for (offset, target) in jumps {
+        if !target.and_then(|target| starts.get(target).copied()).unwrap_or(false) {
+            return Err(LoadError::Malformed(format!("jump at offset {} does not lead to an instruction", offset)));
+        }
+    }
+    Ok(())
+}
+
+pub fn read(bytes: &[u8]) -> Result<Chunk, LoadError> {
+    if !is_compiled(bytes) {
+        return Err(LoadError::NotCompiled);
+    }
+    let mut reader = Reader { bytes, position: MAGIC.len() };
+
+    // Check versions before anything else, older files may not even have a checksum
+    let format_version = reader.u16()?;
+    if format_version != FORMAT_VERSION {
+        return Err(LoadError::UnsupportedFormat(format_version));
+    }
+    let opcode_set_version = reader.u16()?;
+    if opcode_set_version != OPCODE_SET_VERSION {
+        return Err(LoadError::IncompatibleOpcodes(opcode_set_version));
+    }
+
+    if bytes.len() < reader.position + 4 {
+        return Err(LoadError::Truncated);
+    }
+    let (content, stored_checksum) = bytes.split_at(bytes.len() - 4);
+    if checksum(content) != u32::from_le_bytes([stored_checksum[0], stored_checksum[1], stored_checksum[2], stored_checksum[3]]) {
+        return Err(LoadError::ChecksumMismatch);
+    }
+    reader.bytes = content;
+
+    let constant_count = reader.u32()?;
+    let mut constants = Vec::with_capacity(constant_count.min(content.len()));
+    for _ in 0..constant_count {
+        let tag = reader.take(1)?[0];
+        if tag != STRING_CONSTANT {
+            return Err(LoadError::Malformed(format!("unknown constant tag {}", tag)));
+        }
+        constants.push(reader.string()?);
+    }
+
+    let code_length = reader.u32()?;
+    let code = reader.take(code_length)?.to_vec();
+    validate_code(&code, constants.len())?;
+
+    let file = reader.string()?;
+    let line_count = reader.u32()?;
+    let mut lines = Vec::with_capacity(line_count.min(content.len()));
+    for _ in 0..line_count {
+        let offset = reader.u32()?;
+        let position = Position {
+            file: file.clone(),
+            line: reader.u32()?,
+            column: reader.u32()?,
+            start: reader.u32()?,
+            end: reader.u32()?,
+        };
+        lines.push((offset, position));
+    }
+
+    if reader.position != content.len() {
+        return Err(LoadError::Malformed(String::from("trailing bytes after debug info")));
+    }
+    Ok(Chunk { code, lines, constants })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::ByteCode;
+    use crate::compile;
+
+    fn chunk(code: Vec<u8>, constants: &[&str]) -> Chunk {
+        Chunk { code, lines: vec![], constants: constants.iter().map(|constant| constant.to_string()).collect() }
+    }
+
+    // Replace the checksum of a changed file with a valid one, so the check after it is reached
+    fn sign(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.truncate(bytes.len() - 4);
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    fn load_error(bytes: &[u8]) -> String {
+        match read(bytes) {
+            Ok(_) => String::from("loaded"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn written_chunk_is_read_back() {
+        let source = "function f(n: Int) {\n return n * 2\n}\nlet i = 0\n\
+                      while i < 3 {\n printLine(f(i))\n i = i + 1\n}";
+        let original = compile(source.to_string(), "test.yapko", &mut ByteCode::new()).unwrap();
+        let loaded = read(&write(&original)).unwrap();
+        assert_eq!(loaded.code, original.code);
+        assert_eq!(loaded.constants, original.constants);
+        assert_eq!(loaded.lines, original.lines);
+    }
+
+    #[test]
+    fn damaged_and_foreign_files_are_rejected() {
+        let bytes = write(&chunk(vec![OpCode::PushInt as u8, 1, 0, 0, 0, OpCode::Pop as u8], &[]));
+        assert!(read(&bytes).is_ok());
+        assert!(matches!(read(b"printLine(1)"), Err(LoadError::NotCompiled)));
+
+        let mut changed = bytes.clone();
+        changed[20] ^= 1;
+        assert!(matches!(read(&changed), Err(LoadError::ChecksumMismatch)));
+
+        let mut old_format = bytes.clone();
+        old_format[4..6].copy_from_slice(&(FORMAT_VERSION - 1).to_le_bytes());
+        assert!(matches!(
+            read(&old_format),
+            Err(LoadError::UnsupportedFormat(version)) if version == FORMAT_VERSION - 1
+        ));
+        let mut old_opcodes = bytes.clone();
+        old_opcodes[6..8].copy_from_slice(&(OPCODE_SET_VERSION - 1).to_le_bytes());
+        assert!(matches!(read(&old_opcodes), Err(LoadError::IncompatibleOpcodes(_))));
+
+        // Every cut is noticed, either by the checksum or as the end of the file
+        for length in 0..bytes.len() {
+            assert!(read(&bytes[..length]).is_err());
+        }
+        for length in 0..bytes.len() - 4 {
+            assert!(read(&sign([&bytes[..length], &[0; 4]].concat())).is_err());
+        }
+        assert!(matches!(read(&bytes[..6]), Err(LoadError::Truncated)));
+        assert_eq!(load_error(&sign(bytes[..bytes.len() - 8].to_vec())), "unexpected end of file");
+    }
+
+    #[test]
+    fn invalid_code_is_rejected() {
+        let load = |code: Vec<u8>, constants: &[&str]| load_error(&write(&chunk(code, constants)));
+        let get = OpCode::Get as u8;
+        assert_eq!(load(vec![get, 0, 0, 0, 0], &["x"]), "loaded");
+        assert_eq!(load(vec![get, 1, 0, 0, 0], &["x"]), "malformed file: constant 1 at offset 0 does not exist");
+        assert_eq!(load(vec![get, 0, 0], &["x"]), "malformed file: incomplete operand of get at offset 0");
+        assert_eq!(load(vec![255], &[]), "unknown opcode 255 at offset 0");
+
+        let jump = OpCode::Jump as u8;
+        let looping = OpCode::Loop as u8;
+        let nop = OpCode::Nop as u8;
+        // Jumps to the next instruction, to the end of the code and back to the start
+        assert_eq!(load(vec![jump, 1, 0, 0, 0, nop, looping, 11, 0, 0, 0], &[]), "loaded");
+        assert_eq!(
+            load(vec![jump, 2, 0, 0, 0, nop], &[]),
+            "malformed file: jump at offset 0 does not lead to an instruction"
+        );
+        assert_eq!(
+            load(vec![nop, looping, 2, 0, 0, 0], &[]),
+            "malformed file: jump at offset 1 does not lead to an instruction"
+        );
+        assert_eq!(
+            load(vec![looping, 255, 255, 255, 255], &[]),
+            "malformed file: jump at offset 0 does not lead to an instruction"
+        );
+        assert_eq!(
+            load(vec![OpCode::FunStart as u8, 0, 0, 0, 0, 0, 9, 0, 0, 0], &["f"]),
+            "malformed file: jump at offset 0 does not lead to an instruction"
+        );
+    }
+}